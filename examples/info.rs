@@ -73,6 +73,15 @@ fn main() -> Result<()> {
 
     println!("prev set count {}", mot.sets.len());
     let mut qual = mot.qualify(&motset_db, &bone_db);
+    let stats = qual.stats();
+    println!("frames: {}, size: {} bytes", stats.frame_count, stats.total_size());
+    for (ty, count) in &stats.set_types {
+        println!("{:?} sets: {}", ty, count);
+    }
+    for bone in stats.most_expensive(10) {
+        let name = &motset_db.bones[bone.bone];
+        println!("{}: {} bytes, {} keyframes", name, bone.size, bone.keyframes);
+    }
     // for (id, (bone, anim)) in qual.anims.iter().enumerate() {
     //     println!("{:03}: {}\n{:?}", id, bone.name, anim);
     // }
@@ -30,6 +30,10 @@ struct Opt {
     #[structopt(short, long)]
     dont_add_bones: bool,
 
+    /// Frame rate of the bvh
+    #[structopt(long, default_value = "60")]
+    fps: f32,
+
     focus: Option<String>,
 }
 
@@ -242,6 +246,7 @@ fn main() -> Result<()> {
     }
 
     let mut mot = QualifiedMotion { anims };
+    mot.resample(opt.fps, FPS);
     mot.sort(&motset_db);
 
     let mut file = File::create(opt.output)?;
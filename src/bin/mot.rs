@@ -0,0 +1,672 @@
+use anyhow::*;
+use diva_db::bone::*;
+use diva_db::mot::*;
+use mot::farc::Farc;
+use mot::qualified::*;
+use mot::validate::IssueKind;
+use mot::*;
+use nom::number::Endianness;
+use structopt::StructOpt;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+///Looked up in the current directory when `MOT_CONFIG` isn't set
+const CONFIG_FILE: &str = "mot.cfg";
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "mot", about = "Inspects and converts Project DIVA motions")]
+struct Opt {
+    /// Path to mot_db.bin, can also be set in the config file as `mot_db = <path>`
+    #[structopt(long, env = "MOT_DB", global = true, parse(from_os_str))]
+    mot_db: Option<PathBuf>,
+
+    /// Path to bone_data.bin, can also be set in the config file as `bone_db = <path>`
+    #[structopt(long, env = "BONE_DB", global = true, parse(from_os_str))]
+    bone_db: Option<PathBuf>,
+
+    /// Motion to use in files holding several
+    #[structopt(long, default_value = "0", global = true)]
+    index: usize,
+
+    /// Motion to use in files holding several by its name in mot_db, overrides --index
+    #[structopt(long, global = true)]
+    motion: Option<String>,
+
+    /// Entry to use in `.farc` archives, defaults to the archive's name with a `.bin` extension
+    #[structopt(long, global = true)]
+    entry: Option<String>,
+
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Prints the size and keyframe statistics of a motion
+    Info {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+    },
+    /// Prints a motion in the text format
+    Dump {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+    },
+    /// Converts a motion, formats are picked from the extensions
+    ///
+    /// Inputs can be bin, mot, farc, txt or glb, outputs can also be gltf, vmd or csv
+    ///
+    /// Converting to a `.farc` replaces its entry in the archive, creating it if needed
+    Convert {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Lists the issues of a motion, exits with 1 if there are any
+    Validate {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+    },
+    /// Prints what changed between two motions
+    Diff {
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+        /// Smallest difference between the curves to report
+        #[structopt(long, default_value = "0.001")]
+        threshold: f32,
+    },
+    /// Lists the motions of a file, with their set and name when mot_db is given
+    List {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+    },
+    /// Splits a file holding several motions into one file per motion
+    ///
+    /// Files are named by index, followed by the motion's name when mot_db is given
+    Extract {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Packs the motions of a directory into a single file or `.farc` archive entry
+    ///
+    /// Files named after motions of the output's set (`mot_PV001.bin` holds the set `PV001`) are
+    /// packed in the set's order when mot_db is given, other files go in file name order
+    Pack {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+    },
+}
+
+//Reads `key = value` lines, `#` starts a comment
+fn config_value(key: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("MOT_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| CONFIG_FILE.into());
+    let config = fs::read_to_string(path).ok()?;
+    config
+        .lines()
+        .map(|l| l.split('#').next().unwrap_or_default())
+        .filter_map(|l| {
+            let mut kv = l.splitn(2, '=');
+            Some((kv.next()?.trim(), kv.next()?.trim()))
+        })
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v.into())
+}
+
+fn motion_names(mot_db: &MotionSetDatabase, path: &Path) -> Option<Vec<String>> {
+    let set = database::set_of_file(mot_db, path.to_str()?)?;
+    Some(set.motions.iter().map(|x| x.name.clone()).collect())
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("failed to read `{}`", path.display()))
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+//`mot_PV001.farc` holds `mot_PV001.bin`
+fn default_entry(path: &Path) -> String {
+    let stem = path.file_stem().and_then(|x| x.to_str());
+    format!("{}.bin", stem.unwrap_or_default())
+}
+
+fn read_archive(path: &Path) -> Result<Farc> {
+    Farc::read(&read(path)?).with_context(|| format!("failed to read `{}`", path.display()))
+}
+
+//Reads `path`, or its entry when it's an archive
+fn read_data(path: &Path, entry: Option<&str>) -> Result<Vec<u8>> {
+    if extension(path) != "farc" {
+        return read(path);
+    }
+    let mut farc = read_archive(path)?;
+    let name = match entry {
+        Some(name) => name.to_string(),
+        None if farc.entries.len() == 1 => farc.entries[0].name.clone(),
+        None => default_entry(path),
+    };
+    match farc
+        .entries
+        .iter()
+        .position(|x| x.name.eq_ignore_ascii_case(&name))
+    {
+        Some(i) => Ok(farc.entries.swap_remove(i).data),
+        None => {
+            let names: Vec<_> = farc.entries.iter().map(|x| &x.name[..]).collect();
+            bail!(
+                "`{}` has no entry `{}`, it has {}",
+                path.display(),
+                name,
+                names.join(", ")
+            )
+        }
+    }
+}
+
+//Writes `data` to `path`, or replaces its entry when it's an archive
+fn write_data(path: &Path, entry: Option<&str>, data: Vec<u8>) -> Result<()> {
+    if extension(path) != "farc" {
+        return fs::write(path, data)
+            .with_context(|| format!("failed to write `{}`", path.display()));
+    }
+    let mut farc = if path.exists() {
+        read_archive(path)?
+    } else {
+        Farc::default()
+    };
+    let name = entry
+        .map(String::from)
+        .unwrap_or_else(|| default_entry(path));
+    farc.insert(&name, data);
+    let mut out = vec![];
+    farc.write(&mut out)?;
+    fs::write(path, out).with_context(|| format!("failed to write `{}`", path.display()))
+}
+
+struct Paths {
+    mot_db: Option<PathBuf>,
+    bone_db: Option<PathBuf>,
+    index: usize,
+    motion: Option<String>,
+    entry: Option<String>,
+}
+
+impl Paths {
+    fn entry(&self) -> Option<&str> {
+        self.entry.as_deref()
+    }
+
+    //`--motion` has to be in the set stored in `input`
+    fn index(&self, mot_db: &MotionSetDatabase, input: &Path) -> Result<usize> {
+        //Text and glb files hold a single motion
+        if matches!(&extension(input)[..], "txt" | "glb") {
+            return Ok(0);
+        }
+        let name = match &self.motion {
+            Some(name) => name,
+            None => return Ok(self.index),
+        };
+        let file = match self.entry() {
+            Some(entry) if extension(input) == "farc" => entry.into(),
+            _ => input.to_string_lossy(),
+        };
+        database::motion_index(mot_db, &file, name)
+            .map(|(_, i)| i)
+            .map_err(|e| anyhow!(e))
+    }
+
+    fn mot_db(&self) -> Result<MotionSetDatabase> {
+        self.try_mot_db()?
+            .context("mot_db is needed, pass --mot-db or set MOT_DB")
+    }
+
+    //`None` when no mot_db was given
+    fn try_mot_db(&self) -> Result<Option<MotionSetDatabase>> {
+        let path = match self.mot_db.clone().or_else(|| config_value("mot_db")) {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+        let data = read(&path)?;
+        let (_, mot_db) = MotionSetDatabase::read(Endianness::Little)(&data)
+            .map_err(|_| anyhow!("failed to parse mot_db `{}`", path.display()))?;
+        Ok(Some(mot_db))
+    }
+
+    fn bone_data(&self) -> Result<Vec<u8>> {
+        let path = self
+            .bone_db
+            .clone()
+            .or_else(|| config_value("bone_db"))
+            .context("bone_db is needed, pass --bone-db or set BONE_DB")?;
+        read(&path)
+    }
+}
+
+fn bone_db(data: &[u8]) -> Result<BoneDatabase<'_>> {
+    let (_, bone_db) = BoneDatabase::read(data).map_err(|_| anyhow!("failed to parse bone_db"))?;
+    Ok(bone_db)
+}
+
+fn read_motions(path: &Path, entry: Option<&str>) -> Result<Vec<Motion>> {
+    let data = read_data(path, entry)?;
+    let (_, motions) = Motion::parse_set(&data, Endianness::Little)
+        .map_err(|_| anyhow!("failed to parse motion `{}`", path.display()))?;
+    Ok(motions)
+}
+
+//Frame count stored in the header of the motion at `index`
+fn frame_count(path: &Path, entry: Option<&str>, index: usize) -> Result<u16> {
+    let data = read_data(path, entry)?;
+    let (_, counts) = Motion::parse_frame_counts(&data, Endianness::Little)
+        .map_err(|_| anyhow!("failed to parse motion `{}`", path.display()))?;
+    counts
+        .get(index)
+        .copied()
+        .ok_or_else(|| anyhow!("`{}` only has {} motion(s)", path.display(), counts.len()))
+}
+
+fn read_motion(path: &Path, entry: Option<&str>, index: usize) -> Result<Motion> {
+    let mut motions = read_motions(path, entry)?;
+    let len = motions.len();
+    ensure!(
+        index < len,
+        "`{}` only has {} motion(s)",
+        path.display(),
+        len
+    );
+    Ok(motions.swap_remove(index))
+}
+
+fn load(
+    path: &Path,
+    entry: Option<&str>,
+    index: usize,
+    mot_db: &MotionSetDatabase,
+    bone_db: &BoneDatabase,
+) -> Result<QualifiedMotion> {
+    match &extension(path)[..] {
+        "txt" => {
+            let text = fs::read_to_string(path)?;
+            Ok(text::from_text(&text, mot_db)?)
+        }
+        "glb" => Ok(QualifiedMotion::read_glb(&read(path)?, mot_db, bone_db)?),
+        _ => {
+            let mot = read_motion(path, entry, index)?;
+            let frames = frame_count(path, entry, index)?;
+            let issues = mot.validate_with(frames, mot_db, bone_db);
+            //Only these keep the motion from being read, the others are left for the user to fix
+            let fatal = issues.iter().find(|x| {
+                matches!(
+                    x.kind,
+                    IssueKind::BoneOutOfRange(_) | IssueKind::SetCountMismatch { .. }
+                )
+            });
+            if let Some(issue) = fatal {
+                bail!("`{}` is malformed, {}", path.display(), issue);
+            }
+            for issue in &issues {
+                eprintln!("warning: `{}`: {}", path.display(), issue);
+            }
+            Ok(mot.qualify(mot_db, bone_db))
+        }
+    }
+}
+
+//`index` is the motion replaced in `.farc` entries holding several
+fn save(
+    mot: &QualifiedMotion,
+    path: &Path,
+    entry: Option<&str>,
+    index: usize,
+    mot_db: &MotionSetDatabase,
+    bone_db: &BoneDatabase,
+) -> Result<()> {
+    let file =
+        || File::create(path).with_context(|| format!("failed to create `{}`", path.display()));
+    match &extension(path)[..] {
+        "txt" => fs::write(path, text::to_text(mot, mot_db))?,
+        "glb" => {
+            mot.write_glb(mot_db, bone_db, BufWriter::new(file()?))?;
+        }
+        "gltf" => {
+            let bin = path.with_extension("bin");
+            let uri = bin.file_name().and_then(|x| x.to_str()).unwrap_or_default();
+            let bin_file = File::create(&bin)?;
+            mot.write_gltf(
+                mot_db,
+                bone_db,
+                uri,
+                BufWriter::new(file()?),
+                BufWriter::new(bin_file),
+            )?;
+        }
+        "vmd" => {
+            let model = path
+                .file_stem()
+                .and_then(|x| x.to_str())
+                .unwrap_or_default();
+            let skel = bone_db
+                .skeletons
+                .first()
+                .context("bone_db has no skeleton")?;
+            let names = vmd_bone_names();
+            mot.write_vmd(mot_db, skel, &names, model, BufWriter::new(file()?))?;
+        }
+        "csv" => {
+            mot.write_csv(mot_db, CsvOptions::default(), BufWriter::new(file()?))?;
+        }
+        "farc" => {
+            let name = entry
+                .map(String::from)
+                .unwrap_or_else(|| default_entry(path));
+            let old = match path.exists() {
+                true => read_archive(path)?.entry(&name).map(<[u8]>::to_vec),
+                false => None,
+            };
+            let malformed = || anyhow!("failed to parse `{}` in `{}`", name, path.display());
+            let out_of_range = |len| anyhow!("`{}` only has {} motion(s)", name, len);
+            let mut data = vec![];
+            match old {
+                Some(old) => {
+                    let (_, mut motions) =
+                        Motion::parse_set(&old, Endianness::Little).map_err(|_| malformed())?;
+                    let len = motions.len();
+                    *motions.get_mut(index).ok_or_else(|| out_of_range(len))? = mot.to_motion();
+                    Motion::write_set(&motions, &mut data)?;
+                }
+                None if index == 0 => {
+                    Motion::write_set(&[mot.to_motion()], &mut data)?;
+                }
+                None => return Err(out_of_range(0)),
+            }
+            write_data(path, Some(&name), data)?;
+        }
+        _ => {
+            Motion::write_set(&[mot.to_motion()], file()?)?;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    let paths = Paths {
+        mot_db: opt.mot_db,
+        bone_db: opt.bone_db,
+        index: opt.index,
+        motion: opt.motion,
+        entry: opt.entry,
+    };
+    match opt.cmd {
+        Command::Info { input } => {
+            let (mot_db, bone_data) = (paths.mot_db()?, paths.bone_data()?);
+            let bone_db = bone_db(&bone_data)?;
+            let stats = load(
+                &input,
+                paths.entry(),
+                paths.index(&mot_db, &input)?,
+                &mot_db,
+                &bone_db,
+            )?
+            .stats();
+            println!("frames: {}", stats.frame_count);
+            println!(
+                "size: {} bytes (header {}, types {}, keysets {}, bones {})",
+                stats.total_size(),
+                stats.header_size,
+                stats.types_size,
+                stats.keysets_size,
+                stats.bones_size
+            );
+            for (ty, count) in &stats.set_types {
+                println!("{:?} sets: {}", ty, count);
+            }
+            println!("most expensive bones:");
+            for bone in stats.most_expensive(10) {
+                let name = &mot_db.bones[bone.bone];
+                println!(
+                    "  {}: {} bytes, {} keyframes",
+                    name, bone.size, bone.keyframes
+                );
+            }
+        }
+        Command::Dump { input } => {
+            let (mot_db, bone_data) = (paths.mot_db()?, paths.bone_data()?);
+            let bone_db = bone_db(&bone_data)?;
+            let mot = load(
+                &input,
+                paths.entry(),
+                paths.index(&mot_db, &input)?,
+                &mot_db,
+                &bone_db,
+            )?;
+            print!("{}", text::to_text(&mot, &mot_db));
+        }
+        Command::Convert { input, output } => {
+            let (mot_db, bone_data) = (paths.mot_db()?, paths.bone_data()?);
+            let bone_db = bone_db(&bone_data)?;
+            let mot = load(
+                &input,
+                paths.entry(),
+                paths.index(&mot_db, &input)?,
+                &mot_db,
+                &bone_db,
+            )?;
+            let index = match &extension(&output)[..] {
+                "farc" => paths.index(&mot_db, &output)?,
+                _ => 0,
+            };
+            save(&mot, &output, paths.entry(), index, &mot_db, &bone_db)?;
+        }
+        Command::Validate { input } => {
+            let (mot_db, bone_data) = (paths.mot_db()?, paths.bone_data()?);
+            let bone_db = bone_db(&bone_data)?;
+            let index = paths.index(&mot_db, &input)?;
+            let issues = match &extension(&input)[..] {
+                //The frame count of these is taken from their last key when written
+                "txt" | "glb" => {
+                    let mot = load(&input, paths.entry(), index, &mot_db, &bone_db)?;
+                    mot.validate(u16::MAX, &mot_db, &bone_db)
+                }
+                _ => {
+                    let mot = read_motion(&input, paths.entry(), index)?;
+                    let frames = frame_count(&input, paths.entry(), index)?;
+                    match mot.validate_with(frames, &mot_db, &bone_db) {
+                        issues if issues.is_empty() => mot
+                            .qualify(&mot_db, &bone_db)
+                            .validate(frames, &mot_db, &bone_db),
+                        issues => issues,
+                    }
+                }
+            };
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            if !issues.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::Diff { a, b, threshold } => {
+            let (mot_db, bone_data) = (paths.mot_db()?, paths.bone_data()?);
+            let bone_db = bone_db(&bone_data)?;
+            let a = load(
+                &a,
+                paths.entry(),
+                paths.index(&mot_db, &a)?,
+                &mot_db,
+                &bone_db,
+            )?;
+            let b = load(
+                &b,
+                paths.entry(),
+                paths.index(&mot_db, &b)?,
+                &mot_db,
+                &bone_db,
+            )?;
+            print!("{}", diff::report(&diff::diff(&a, &b, threshold), &mot_db));
+        }
+        Command::List { input } => {
+            let mot_db = paths.try_mot_db()?;
+            let set = mot_db
+                .as_ref()
+                .and_then(|db| database::set_of_file(db, input.to_str()?));
+            for (i, mot) in read_motions(&input, paths.entry())?.iter().enumerate() {
+                let bones = mot.bones.len();
+                match set.and_then(|set| Some((set, set.motions.get(i)?))) {
+                    Some((set, info)) => println!(
+                        "{:03}: {}/{} (id {}), {} bones",
+                        i, set.name, info.name, info.id, bones
+                    ),
+                    None => println!("{:03}: {} bones", i, bones),
+                }
+            }
+        }
+        Command::Extract { input, output } => {
+            let mot_db = paths.try_mot_db()?;
+            let names = mot_db.as_ref().and_then(|db| motion_names(db, &input));
+            fs::create_dir_all(&output)?;
+            for (i, mot) in read_motions(&input, paths.entry())?.iter().enumerate() {
+                let name = match names.as_ref().and_then(|x| x.get(i)) {
+                    Some(name) => format!("{:03}_{}.bin", i, name),
+                    None => format!("{:03}.bin", i),
+                };
+                let file = BufWriter::new(File::create(output.join(name))?);
+                Motion::write_set(std::slice::from_ref(mot), file)?;
+            }
+        }
+        Command::Pack { input, output } => {
+            let mut files: Vec<PathBuf> = fs::read_dir(&input)?
+                .map(|x| x.map(|x| x.path()))
+                .collect::<Result<_, _>>()?;
+            files.retain(|x| extension(x) == "bin");
+            files.sort();
+            //Files named after a motion of the output's set go in the set's order
+            let mot_db = paths.try_mot_db()?;
+            if let Some(names) = mot_db.as_ref().and_then(|db| motion_names(db, &output)) {
+                files.sort_by_key(|file| {
+                    let stem = file
+                        .file_stem()
+                        .and_then(|x| x.to_str())
+                        .unwrap_or_default();
+                    let name = stem.trim_start_matches(|c: char| c.is_ascii_digit());
+                    let name = name.strip_prefix('_').unwrap_or(name);
+                    names.iter().position(|x| x == name).unwrap_or(names.len())
+                });
+                if files.len() != names.len() {
+                    eprintln!(
+                        "warning: packing {} file(s) but the motion set has {} motion(s)",
+                        files.len(),
+                        names.len()
+                    );
+                }
+            }
+            let mut motions = vec![];
+            for file in &files {
+                motions.append(&mut read_motions(file, None)?);
+            }
+            let mut data = vec![];
+            Motion::write_set(&motions, &mut data)?;
+            write_data(&output, paths.entry(), data)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BONE_DATA: &[u8] = include_bytes!("../../assets/bone_data.bin");
+
+    //A new directory for every call, so parallel tests and test runs don't share files
+    fn temp_dir(name: &str) -> PathBuf {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("mot_{}_{}_{}", name, std::process::id(), n));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn dump_convert_roundtrip() {
+        let mot_db = MotionSetDatabase {
+            bones: vec!["gblctr".into(), "kl_kubi".into()],
+            ..Default::default()
+        };
+        let bone_db = bone_db(BONE_DATA).unwrap();
+        let key = |frame, value| Keyframe { frame, value };
+        let rot = Vec3 {
+            x: FrameData::Linear(vec![key(0, 0.), key(2, 1.), key(5, 0.5)]),
+            ..Vec3::ZERO
+        };
+        let pos = Vec3 {
+            y: FrameData::Pose(1.25),
+            ..Vec3::ZERO
+        };
+        let mot = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(pos))),
+                (1, Some(BoneAnim::Rotation(rot))),
+            ],
+        };
+
+        let dir = temp_dir("dump_convert_roundtrip");
+        let (bin, txt, out) = (dir.join("x.bin"), dir.join("x.txt"), dir.join("y.bin"));
+        save(&mot, &bin, None, 0, &mot_db, &bone_db).unwrap();
+        //What `mot dump x.bin > x.txt` writes
+        let dump = text::to_text(&load(&bin, None, 0, &mot_db, &bone_db).unwrap(), &mot_db);
+        fs::write(&txt, &dump).unwrap();
+        //`mot convert x.txt y.bin`
+        let converted = load(&txt, None, 0, &mot_db, &bone_db).unwrap();
+        save(&converted, &out, None, 0, &mot_db, &bone_db).unwrap();
+
+        let read = load(&out, None, 0, &mot_db, &bone_db).unwrap();
+        assert_eq!(text::to_text(&read, &mot_db), dump);
+        assert!(dump.contains("    0 0\n    2 1\n    5 0.5\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_farc_keeps_other_motions() {
+        let mot_db = MotionSetDatabase {
+            bones: vec!["gblctr".into()],
+            ..Default::default()
+        };
+        let bone_db = bone_db(BONE_DATA).unwrap();
+        let pose = |y| Motion {
+            sets: vec![FrameData::None, FrameData::Pose(y), FrameData::None],
+            bones: vec![0],
+        };
+        let mut entry = vec![];
+        Motion::write_set(&[pose(1.), pose(2.)], &mut entry).unwrap();
+
+        let dir = temp_dir("save_farc_keeps_other_motions");
+        let path = dir.join("mot_PV001.farc");
+        write_data(&path, None, entry).unwrap();
+        let mot = pose(3.).qualify(&mot_db, &bone_db);
+        save(&mot, &path, None, 1, &mot_db, &bone_db).unwrap();
+        assert!(save(&mot, &path, None, 2, &mot_db, &bone_db).is_err());
+
+        let motions = read_motions(&path, None).unwrap();
+        let poses: Vec<_> = motions.iter().map(|x| x.sets[1].clone()).collect();
+        assert_eq!(poses, [FrameData::Pose(1.), FrameData::Pose(3.)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
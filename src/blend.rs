@@ -0,0 +1,183 @@
+use super::interpolate::bake;
+use super::qualified::*;
+use super::rotation::{bake_rotation, euler_to_quat};
+use super::*;
+
+use cgmath::InnerSpace;
+
+use std::collections::HashMap;
+use std::mem::discriminant;
+
+fn blend_position(a: &Vec3, b: &Vec3, weight: f32, len: u16) -> Vec3 {
+    bake(len, |frame| {
+        let (a, b) = (a.interpolate(frame as f32), b.interpolate(frame as f32));
+        a + (b - a) * weight
+    })
+}
+
+fn blend_rotation(a: &Vec3, b: &Vec3, weight: f32, len: u16) -> Vec3 {
+    bake_rotation(len, a.interpolate(0.), |frame| {
+        let (ea, eb) = (a.interpolate(frame as f32), b.interpolate(frame as f32));
+        let qa = euler_to_quat(ea.x, ea.y, ea.z);
+        let mut qb = euler_to_quat(eb.x, eb.y, eb.z);
+        //Take the shortest path
+        if qa.dot(qb) < 0. {
+            qb = -qb;
+        }
+        qa.slerp(qb, weight)
+    })
+}
+
+impl BoneAnim {
+    ///The animation with every curve set to 0, which is how the game poses bones without one
+    fn rest(&self) -> BoneAnim {
+        let mut rest = self.clone();
+        for (_, v) in rest.curves_mut() {
+            for set in v.sets_mut().iter_mut() {
+                **set = FrameData::Pose(0.);
+            }
+        }
+        rest
+    }
+
+    fn blend(&self, other: &BoneAnim, weight: f32, len: u16) -> BoneAnim {
+        let mut out = self.clone();
+        for ((name, v), (_, o)) in out.curves_mut().into_iter().zip(other.curves()) {
+            *v = match name {
+                "rotation" => blend_rotation(v, o, weight, len),
+                _ => blend_position(v, o, weight, len),
+            };
+        }
+        out
+    }
+}
+
+impl QualifiedMotion {
+    ///Blends the bones of `b` listed in `mask` into `a`, other bones are taken from `a`
+    ///
+    ///Each bone is blended with its weight in `weights`, or `weight` when it isn't listed.
+    ///A weight of 0 keeps `a` and 1 replaces it with `b`. Rotations are interpolated as
+    ///quaternions and every blended bone is baked to `Linear` keyframes. Bones only animated
+    ///by `b` are blended from their rest pose, bones with a different type in each motion are
+    ///kept from `a`.
+    ///
+    ///Layering several motions is done by blending one at a time, e.g. the legs of a walk
+    ///over an upper body motion and then a face motion over the result
+    pub fn blend(
+        a: &QualifiedMotion,
+        b: &QualifiedMotion,
+        weight: f32,
+        weights: &HashMap<usize, f32>,
+        mask: &[usize],
+    ) -> QualifiedMotion {
+        let len = a.get_max_keyframe().max(b.get_max_keyframe());
+        let find = |id: usize| {
+            b.anims
+                .iter()
+                .find(|(i, _)| *i == id)
+                .and_then(|(_, a)| a.as_ref())
+        };
+        let weight = |id: usize| weights.get(&id).cloned().unwrap_or(weight);
+        let from_rest = |id: usize, y: &BoneAnim| match weight(id) {
+            w if w >= 1. => y.clone(),
+            w => y.rest().blend(y, w, len),
+        };
+        let mut anims = vec![];
+        for (id, anim) in &a.anims {
+            let blended = match (anim, find(*id)) {
+                (Some(x), Some(y)) if mask.contains(id) && discriminant(x) == discriminant(y) => {
+                    Some(x.blend(y, weight(*id), len))
+                }
+                (None, Some(y)) if mask.contains(id) => Some(from_rest(*id, y)),
+                _ => anim.clone(),
+            };
+            anims.push((*id, blended));
+        }
+        for (id, anim) in &b.anims {
+            if mask.contains(id) && !a.anims.iter().any(|(i, _)| i == id) {
+                anims.push((*id, anim.as_ref().map(|y| from_rest(*id, y))));
+            }
+        }
+        QualifiedMotion { anims }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rotation(x: f32, z: f32) -> Option<BoneAnim> {
+        Some(BoneAnim::Rotation(Vec3 {
+            x: FrameData::Pose(x),
+            y: FrameData::None,
+            z: FrameData::Linear(vec![
+                Keyframe {
+                    frame: 0,
+                    value: 0.,
+                },
+                Keyframe {
+                    frame: 10,
+                    value: z,
+                },
+            ]),
+        }))
+    }
+
+    #[test]
+    fn blend_masked() {
+        let a = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(Vec3::ZERO))),
+                (1, rotation(0., 1.)),
+            ],
+        };
+        let b = QualifiedMotion {
+            anims: vec![
+                (
+                    0,
+                    Some(BoneAnim::Position(Vec3 {
+                        x: FrameData::Pose(2.),
+                        ..Vec3::ZERO
+                    })),
+                ),
+                (1, rotation(0., -1.)),
+                (2, rotation(1., 0.)),
+            ],
+        };
+        let mot = QualifiedMotion::blend(&a, &b, 0.5, &HashMap::new(), &[1, 2]);
+        assert_eq!(mot.anims[0], (0, Some(BoneAnim::Position(Vec3::ZERO))));
+        match &mot.anims[1].1 {
+            Some(BoneAnim::Rotation(v)) => {
+                assert!(v.interpolate(10.).magnitude() < 1e-4);
+            }
+            e => panic!("unexpected anim {:?}", e),
+        }
+        //Bones only in `b` are faded in from their rest pose
+        match &mot.anims[2].1 {
+            Some(BoneAnim::Rotation(v)) => assert!((v.x.interpolate(0.) - 0.5).abs() < 1e-4),
+            e => panic!("unexpected anim {:?}", e),
+        }
+        let weights = [(2, 1.)].iter().cloned().collect();
+        let mot = QualifiedMotion::blend(&a, &b, 0.5, &weights, &[1, 2]);
+        assert_eq!(mot.anims[2], (2, rotation(1., 0.)));
+
+        let weights = [(0, 0.25)].iter().cloned().collect();
+        let mot = QualifiedMotion::blend(&a, &b, 0.5, &weights, &[0]);
+        match &mot.anims[0].1 {
+            Some(BoneAnim::Position(v)) => assert_eq!(v.x, FrameData::Pose(0.5)),
+            e => panic!("unexpected anim {:?}", e),
+        }
+    }
+
+    #[test]
+    fn blend_rotation_slerp() {
+        let a = Vec3::ZERO;
+        let b = Vec3 {
+            z: FrameData::Pose(1.),
+            ..Vec3::ZERO
+        };
+        let v = blend_rotation(&a, &b, 0.25, 2);
+        assert!((v.z.interpolate(0.) - 0.25).abs() < 1e-5);
+        assert!(v.x.interpolate(0.).abs() < 1e-5);
+    }
+}
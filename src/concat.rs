@@ -0,0 +1,336 @@
+use super::qualified::*;
+use super::*;
+
+use diva_db::mot::MotionSetDatabase;
+use std::fmt;
+use std::mem::discriminant;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConcatError {
+    ///A bone animated with a different type in each of the joined motions
+    Mismatch { id: usize },
+    ///The joined motion has keyframes past `u16::MAX`
+    TooLong,
+}
+
+impl fmt::Display for ConcatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConcatError::Mismatch { id } => {
+                write!(f, "bone {} has a different type in each motion", id)
+            }
+            ConcatError::TooLong => {
+                write!(f, "the joined motion is longer than {} frames", u16::MAX)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConcatError {}
+
+fn key(frame: u16, value: f32, interpolation: f32) -> InterpKeyframe {
+    InterpKeyframe {
+        keyframe: Keyframe { frame, value },
+        interpolation,
+    }
+}
+
+//Eases in and out of the cross-fade, returns the weight and its slope per frame
+pub(crate) fn cross_fade(i: u16, len: u16) -> (f32, f32) {
+    let t = i as f32 / len as f32;
+    (t * t * (3. - 2. * t), 6. * t * (1. - t) / len as f32)
+}
+
+impl FrameData {
+    ///Plays `self` then `other` starting at frame `start`, fading from one to the other over
+    ///`blend` frames
+    ///
+    ///The result is `Smooth` when both sides are, otherwise `Smooth` sides get baked into
+    ///one `Linear` keyframe per frame
+    fn concat(&self, other: &FrameData, start: u16, blend: u16) -> Result<FrameData, ConcatError> {
+        use FrameData::*;
+        match (self, other) {
+            (None, None) => return Ok(None),
+            (Pose(a), Pose(b)) if a == b => return Ok(Pose(*a)),
+            _ => (),
+        }
+        let smooth = match (self, other) {
+            (Linear(_), _) | (_, Linear(_)) => false,
+            (Smooth(_), _) | (_, Smooth(_)) => true,
+            _ => false,
+        };
+        let keys = |set: &FrameData| -> Vec<InterpKeyframe> {
+            match set {
+                None | Pose(_) => vec![key(0, set.interpolate(0.), 0.)],
+                Linear(l) => l.iter().map(|k| key(k.frame, k.value, 0.)).collect(),
+                Smooth(l) if smooth => l.clone(),
+                Smooth(l) if l.is_empty() => vec![],
+                Smooth(l) => (l[0].keyframe.frame..=l[l.len() - 1].keyframe.frame)
+                    .map(|f| key(f, set.interpolate(f as f32), 0.))
+                    .collect(),
+            }
+        };
+
+        //Without a blend the last frame of `self` has to be kept as a keyframe
+        let head_end = if blend == 0 {
+            start.saturating_sub(1)
+        } else {
+            start
+        };
+        let mut head = self.clone();
+        head.trim(0, head_end);
+        let mut out: Vec<InterpKeyframe> = keys(&head)
+            .into_iter()
+            .filter(|k| k.keyframe.frame < start)
+            .collect();
+        for i in 0..blend {
+            let frame = start + i;
+            let (w, dw) = cross_fade(i, blend);
+            let (a, b) = (self.interpolate(frame as f32), other.interpolate(i as f32));
+            let (ta, tb) = (self.tangents(frame as f32).1, other.tangents(i as f32).1);
+            out.push(key(
+                frame,
+                a + (b - a) * w,
+                ta * (1. - w) + tb * w + (b - a) * dw,
+            ));
+        }
+        let mut tail = other.clone();
+        let last = other.keyframes().last().cloned().unwrap_or(0);
+        tail.trim(blend, last.max(blend));
+        for mut k in keys(&tail) {
+            k.keyframe.frame = k
+                .keyframe
+                .frame
+                .checked_add(start + blend)
+                .ok_or(ConcatError::TooLong)?;
+            out.push(k);
+        }
+
+        Ok(if smooth {
+            Smooth(out)
+        } else {
+            Linear(out.into_iter().map(|k| k.keyframe).collect())
+        })
+    }
+}
+
+impl Vec3 {
+    fn concat(&self, other: &Vec3, start: u16, blend: u16) -> Result<Vec3, ConcatError> {
+        Ok(Vec3 {
+            x: self.x.concat(&other.x, start, blend)?,
+            y: self.y.concat(&other.y, start, blend)?,
+            z: self.z.concat(&other.z, start, blend)?,
+        })
+    }
+}
+
+impl BoneAnim {
+    ///The animation held at `frame`
+    fn pose_at(&self, frame: u16) -> BoneAnim {
+        let mut pose = self.clone();
+        for (_, v) in pose.curves_mut() {
+            for set in v.sets_mut().iter_mut() {
+                if **set != FrameData::None {
+                    **set = FrameData::Pose(set.interpolate(frame as f32));
+                }
+            }
+        }
+        pose
+    }
+
+    fn concat(&self, other: &BoneAnim, start: u16, blend: u16) -> Result<BoneAnim, ConcatError> {
+        let mut out = self.clone();
+        for ((_, v), (_, o)) in out.curves_mut().into_iter().zip(other.curves()) {
+            *v = v.concat(o, start, blend)?;
+        }
+        Ok(out)
+    }
+}
+
+impl QualifiedMotion {
+    ///Appends `other` to the motion, cross-fading the two over `blend_frames` frames
+    ///
+    ///Bones are matched by id, a bone only animated by one of the motions keeps its pose at the
+    ///boundary during the other one. The two motions overlap during the blend, so the result is
+    ///that many frames shorter than both put together. The bones are sorted for `mot_db`.
+    ///Fails when a bone has a different type in each motion or the result is too long
+    pub fn concat(
+        &self,
+        other: &QualifiedMotion,
+        blend_frames: u16,
+        mot_db: &MotionSetDatabase,
+    ) -> Result<QualifiedMotion, ConcatError> {
+        let len = self.get_max_keyframe();
+        let blend = blend_frames
+            .min(len.saturating_sub(1))
+            .min(other.get_max_keyframe().saturating_sub(1));
+        let start = len - blend;
+        let find = |mot: &QualifiedMotion, id: usize| {
+            mot.anims
+                .iter()
+                .find(|(i, _)| *i == id)
+                .and_then(|(_, a)| a.clone())
+        };
+
+        let mut anims = vec![];
+        for (id, a) in &self.anims {
+            let anim = match (a, find(other, *id)) {
+                (Some(a), Some(b)) if discriminant(a) != discriminant(&b) => {
+                    return Err(ConcatError::Mismatch { id: *id })
+                }
+                (Some(a), Some(b)) => Some(a.concat(&b, start, blend)?),
+                (Some(a), None) => {
+                    Some(a.concat(&a.pose_at(len.saturating_sub(1)), start, blend)?)
+                }
+                (None, Some(b)) => Some(b.pose_at(0).concat(&b, start, blend)?),
+                (None, None) => None,
+            };
+            anims.push((*id, anim));
+        }
+        for (id, b) in &other.anims {
+            if self.anims.iter().any(|(i, _)| i == id) {
+                continue;
+            }
+            let anim = match b {
+                Some(b) => Some(b.pose_at(0).concat(b, start, blend)?),
+                None => None,
+            };
+            anims.push((*id, anim));
+        }
+        let mut mot = QualifiedMotion { anims };
+        mot.sort(mot_db);
+        Ok(mot)
+    }
+
+    ///Inserts `other` at `at_frame`, the rest of the motion plays once `other` is done
+    ///
+    ///Both boundaries are cross-faded over `blend_frames` frames like `concat`, so each of them
+    ///overlaps its two sides and the result is up to `2 * blend_frames` frames shorter than
+    ///both motions put together
+    pub fn splice(
+        &self,
+        at_frame: u16,
+        other: &QualifiedMotion,
+        blend_frames: u16,
+        mot_db: &MotionSetDatabase,
+    ) -> Result<QualifiedMotion, ConcatError> {
+        let end = self.get_max_keyframe().saturating_sub(1);
+        let mut tail = self.clone();
+        tail.trim(at_frame.min(end), end);
+        if at_frame == 0 {
+            return other.concat(&tail, blend_frames, mot_db);
+        }
+        let mut head = self.clone();
+        head.trim(0, at_frame - 1);
+        head.concat(other, blend_frames, mot_db)?
+            .concat(&tail, blend_frames, mot_db)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    fn ramp(from: f32, to: f32, len: u16) -> QualifiedMotion {
+        let x = FrameData::Linear(vec![
+            Keyframe {
+                frame: 0,
+                value: from,
+            },
+            Keyframe {
+                frame: len - 1,
+                value: to,
+            },
+        ]);
+        let rot = Vec3 { x, ..Vec3::ZERO };
+        QualifiedMotion {
+            anims: vec![(1, Some(BoneAnim::Rotation(rot)))],
+        }
+    }
+
+    fn x(mot: &QualifiedMotion, id: usize) -> &FrameData {
+        match &mot.anims.iter().find(|(i, _)| *i == id).unwrap().1 {
+            Some(BoneAnim::Rotation(v)) | Some(BoneAnim::Position(v)) => &v.x,
+            e => panic!("unexpected anim {:?}", e),
+        }
+    }
+
+    #[test]
+    fn concat_hard_cut() {
+        let a = ramp(0., 1., 11);
+        let b = ramp(2., 3., 11);
+        let mot = a.concat(&b, 0, &mot_db()).unwrap();
+        assert_eq!(mot.get_max_keyframe(), 22);
+        assert_eq!(x(&mot, 1).interpolate(10.), 1.);
+        assert_eq!(x(&mot, 1).interpolate(11.), 2.);
+        assert_eq!(x(&mot, 1).interpolate(21.), 3.);
+    }
+
+    #[test]
+    fn concat_blend() {
+        let a = ramp(0., 0., 11);
+        let mut b = ramp(1., 1., 11);
+        b.anims.push((0, Some(BoneAnim::Position(Vec3::ZERO))));
+        let mot = a.concat(&b, 4, &mot_db()).unwrap();
+        assert_eq!(mot.get_max_keyframe(), 18);
+        let set = x(&mot, 1);
+        assert_eq!(set.interpolate(7.), 0.);
+        assert_eq!(set.interpolate(9.), 0.5);
+        assert_eq!(set.interpolate(11.), 1.);
+        //Bones only in `other` hold their first pose
+        assert_eq!(x(&mot, 0), &FrameData::Pose(0.));
+        assert_eq!(mot.anims[0].0, bone_id("gblctr"));
+    }
+
+    #[test]
+    fn splice_middle() {
+        let a = ramp(0., 10., 11);
+        let b = ramp(-1., -1., 3);
+        let mot = a.splice(5, &b, 0, &mot_db()).unwrap();
+        assert_eq!(mot.get_max_keyframe(), 14);
+        let set = x(&mot, 1);
+        assert_eq!(set.interpolate(4.), 4.);
+        assert_eq!(set.interpolate(6.), -1.);
+        assert_eq!(set.interpolate(8.), 5.);
+        assert_eq!(set.interpolate(13.), 10.);
+
+        //Fading in and out of `other` overlaps it with both sides
+        let mot = a.splice(5, &b, 2, &mot_db()).unwrap();
+        assert_eq!(mot.get_max_keyframe(), 10);
+        let set = x(&mot, 1);
+        assert_eq!(set.interpolate(2.), 2.);
+        assert!(set.interpolate(5.) < 5.);
+        assert_eq!(set.interpolate(9.), 10.);
+    }
+
+    #[test]
+    fn concat_mismatched() {
+        let a = ramp(0., 1., 11);
+        let mut b = a.clone();
+        b.anims[0].1 = Some(BoneAnim::Position(Vec3::ZERO));
+        assert_eq!(
+            a.concat(&b, 0, &mot_db()),
+            Err(ConcatError::Mismatch { id: 1 })
+        );
+        assert_eq!(
+            a.splice(5, &b, 2, &mot_db()),
+            Err(ConcatError::Mismatch { id: 1 })
+        );
+    }
+
+    #[test]
+    fn empty_motion() {
+        let mot = QualifiedMotion {
+            anims: vec![(0, None)],
+        };
+        assert_eq!(mot.concat(&mot, 4, &mot_db()), Ok(mot.clone()));
+        assert_eq!(mot.splice(2, &mot, 4, &mot_db()), Ok(mot.clone()));
+    }
+
+    #[test]
+    fn concat_too_long() {
+        let a = ramp(0., 1., 40000);
+        assert_eq!(a.concat(&a, 0, &mot_db()), Err(ConcatError::TooLong));
+    }
+}
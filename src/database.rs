@@ -0,0 +1,151 @@
+//! Lookups of motions through the motion set database
+//!
+//! Every motion set is stored in its own file, `mot_PV001.bin` holds the set `PV001`, and the
+//! motions of the file are in the same order as the set's `motions`
+use super::*;
+
+use diva_db::mot::*;
+use nom::number::Endianness;
+
+///The motion set stored in the file `file_name`
+///
+///Accepts paths, with or without the `mot_` prefix and extension, ignoring case
+pub fn set_of_file<'a>(
+    mot_db: &'a MotionSetDatabase,
+    file_name: &str,
+) -> Option<&'a MotionSetInfo> {
+    let name = file_name.rsplit(['/', '\\']).next()?;
+    let name = name.split('.').next()?;
+    let name = match name.get(..4) {
+        Some(prefix) if name.len() > 4 && prefix.eq_ignore_ascii_case("mot_") => &name[4..],
+        _ => name,
+    };
+    mot_db
+        .sets
+        .iter()
+        .find(|x| x.name.eq_ignore_ascii_case(name))
+}
+
+///The set holding the motion `name` and the motion's index in its file
+pub fn find_motion<'a>(
+    mot_db: &'a MotionSetDatabase,
+    name: &str,
+) -> Option<(&'a MotionSetInfo, usize)> {
+    mot_db.sets.iter().find_map(|set| {
+        set.motions
+            .iter()
+            .position(|x| x.name.eq_ignore_ascii_case(name))
+            .map(|i| (set, i))
+    })
+}
+
+///The set holding the motion `name` and its index, if the set is stored in `file_name`
+pub fn motion_index<'a>(
+    mot_db: &'a MotionSetDatabase,
+    file_name: &str,
+    name: &str,
+) -> Result<(&'a MotionSetInfo, usize), String> {
+    let (set, index) =
+        find_motion(mot_db, name).ok_or_else(|| format!("motion `{}` isn't in mot_db", name))?;
+    match set_of_file(mot_db, file_name) {
+        Some(file_set) if file_set.id == set.id => Ok((set, index)),
+        Some(file_set) => Err(format!(
+            "`{}` is in the set `{}` but `{}` holds `{}`",
+            name, set.name, file_name, file_set.name
+        )),
+        None => Err(format!(
+            "`{}` isn't the file of a set in mot_db, `{}` is in `{}`",
+            file_name, name, set.name
+        )),
+    }
+}
+
+impl Motion {
+    ///Parses the motion `name` out of the file holding its set, named `file_name`
+    pub fn parse_by_name(
+        i: &[u8],
+        endian: Endianness,
+        mot_db: &MotionSetDatabase,
+        file_name: &str,
+        name: &str,
+    ) -> Result<Self, String> {
+        let (set, index) = motion_index(mot_db, file_name, name)?;
+        let (_, mut motions) = Motion::parse_set(i, endian)
+            .map_err(|_| format!("failed to parse the motions of `{}`", set.name))?;
+        if index >= motions.len() {
+            return Err(format!(
+                "`{}` is motion {} of `{}` but the file only has {}",
+                name,
+                index,
+                set.name,
+                motions.len()
+            ));
+        }
+        Ok(motions.swap_remove(index))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mot_db() -> MotionSetDatabase {
+        let motion = |name: &str, id| MotionInfo {
+            name: name.into(),
+            id,
+        };
+        MotionSetDatabase {
+            sets: vec![
+                MotionSetInfo {
+                    name: "PV001".into(),
+                    id: 1,
+                    motions: vec![motion("PV001_MIK_ALL", 10), motion("PV001_MIK_FACE", 11)],
+                },
+                MotionSetInfo {
+                    name: "PV002".into(),
+                    id: 2,
+                    motions: vec![motion("PV002_MIK_ALL", 20)],
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lookup_names() {
+        let mot_db = mot_db();
+        assert_eq!(
+            set_of_file(&mot_db, "rom/rob/mot_PV002.bin").map(|x| x.id),
+            Some(2)
+        );
+        assert_eq!(set_of_file(&mot_db, "pv001").map(|x| x.id), Some(1));
+        assert!(set_of_file(&mot_db, "mot_PV003.bin").is_none());
+        assert!(set_of_file(&mot_db, "mot").is_none());
+        assert!(set_of_file(&mot_db, "mö_PV001.bin").is_none());
+        let (set, index) = find_motion(&mot_db, "PV001_MIK_FACE").unwrap();
+        assert_eq!((set.id, index), (1, 1));
+        assert_eq!(
+            motion_index(&mot_db, "mot_PV001.bin", "PV002_MIK_ALL").unwrap_err(),
+            "`PV002_MIK_ALL` is in the set `PV002` but `mot_PV001.bin` holds `PV001`"
+        );
+    }
+
+    #[test]
+    fn parse_named() {
+        let mot = |pose| Motion {
+            sets: vec![FrameData::Pose(pose)],
+            bones: vec![0],
+        };
+        let mut file = vec![];
+        Motion::write_set(&[mot(1.), mot(2.)], &mut file).unwrap();
+        let mot_db = mot_db();
+        let parse = |file_name, name| {
+            Motion::parse_by_name(&file, Endianness::Little, &mot_db, file_name, name)
+        };
+        let read = parse("mot_PV001.bin", "PV001_MIK_FACE").unwrap();
+        assert_eq!(read.sets, vec![FrameData::Pose(2.)]);
+        assert!(parse("mot_PV001.bin", "PV003_MIK_ALL").is_err());
+        assert!(parse("mot_PV001.bin", "PV002_MIK_ALL").is_err());
+        assert!(parse("motion.bin", "PV001_MIK_FACE").is_err());
+    }
+}
@@ -0,0 +1,241 @@
+//! Structural comparison of two qualified motions
+use super::qualified::*;
+use super::*;
+
+use diva_db::mot::MotionSetDatabase;
+
+use std::fmt::Write;
+use std::mem::discriminant;
+
+///A single set of a bone, e.g. the x axis of `rotation`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub bone: usize,
+    pub curve: &'static str,
+    pub axis: char,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    BoneAdded(usize),
+    BoneRemoved(usize),
+    ///The bone is animated differently, its curves aren't compared
+    AnimChanged(usize),
+    SetTypeChanged {
+        channel: Channel,
+        from: &'static str,
+        to: &'static str,
+    },
+    KeyframesInserted {
+        channel: Channel,
+        frames: Vec<u16>,
+    },
+    KeyframesRemoved {
+        channel: Channel,
+        frames: Vec<u16>,
+    },
+    ///Largest difference between the evaluated curves
+    Deviation {
+        channel: Channel,
+        frame: u16,
+        delta: f32,
+    },
+}
+
+fn set_name(set: &FrameData) -> &'static str {
+    match set {
+        FrameData::None => "none",
+        FrameData::Pose(_) => "pose",
+        FrameData::Linear(_) => "linear",
+        FrameData::Smooth(_) => "smooth",
+    }
+}
+
+fn diff_set(
+    a: &FrameData,
+    b: &FrameData,
+    channel: Channel,
+    len: u16,
+    threshold: f32,
+) -> Vec<Change> {
+    let mut out = vec![];
+    if discriminant(a) != discriminant(b) {
+        out.push(Change::SetTypeChanged {
+            channel: channel.clone(),
+            from: set_name(a),
+            to: set_name(b),
+        });
+    }
+    let (ka, kb) = (a.keyframes(), b.keyframes());
+    let inserted: Vec<u16> = kb.iter().filter(|f| !ka.contains(f)).cloned().collect();
+    let removed: Vec<u16> = ka.iter().filter(|f| !kb.contains(f)).cloned().collect();
+    if !inserted.is_empty() {
+        out.push(Change::KeyframesInserted {
+            channel: channel.clone(),
+            frames: inserted,
+        });
+    }
+    if !removed.is_empty() {
+        out.push(Change::KeyframesRemoved {
+            channel: channel.clone(),
+            frames: removed,
+        });
+    }
+    let worst = (0..len)
+        .map(|f| (f, b.interpolate(f as f32) - a.interpolate(f as f32)))
+        .fold((0, 0f32), |w, x| if x.1.abs() > w.1.abs() { x } else { w });
+    if worst.1.abs() > threshold {
+        out.push(Change::Deviation {
+            channel,
+            frame: worst.0,
+            delta: worst.1,
+        });
+    }
+    out
+}
+
+fn find(mot: &QualifiedMotion, id: usize) -> Option<&Option<BoneAnim>> {
+    mot.anims.iter().find(|(i, _)| *i == id).map(|(_, a)| a)
+}
+
+///Changes needed to go from `a` to `b`, bones are matched by id
+///
+///Curves are evaluated on every frame of the longer motion and only deviations larger than
+///`threshold` are reported
+pub fn diff(a: &QualifiedMotion, b: &QualifiedMotion, threshold: f32) -> Vec<Change> {
+    let len = a.get_max_keyframe().max(b.get_max_keyframe());
+    let mut out = vec![];
+    for (id, anim) in &a.anims {
+        let other = match find(b, *id) {
+            Some(o) => o,
+            None => {
+                out.push(Change::BoneRemoved(*id));
+                continue;
+            }
+        };
+        let (x, y) = match (anim, other) {
+            (Some(x), Some(y)) if discriminant(x) == discriminant(y) => (x, y),
+            (None, None) => continue,
+            _ => {
+                out.push(Change::AnimChanged(*id));
+                continue;
+            }
+        };
+        for ((curve, v0), (_, v1)) in x.curves().into_iter().zip(y.curves()) {
+            for ((axis, s0), s1) in ['x', 'y', 'z']
+                .iter()
+                .zip(v0.sets().iter())
+                .zip(v1.sets().iter())
+            {
+                let channel = Channel {
+                    bone: *id,
+                    curve,
+                    axis: *axis,
+                };
+                out.append(&mut diff_set(s0, s1, channel, len, threshold));
+            }
+        }
+    }
+    for (id, _) in &b.anims {
+        if find(a, *id).is_none() {
+            out.push(Change::BoneAdded(*id));
+        }
+    }
+    out
+}
+
+///Prints one change per line with bone names taken from `mot_db`
+pub fn report(changes: &[Change], mot_db: &MotionSetDatabase) -> String {
+    let name = |id: usize| {
+        mot_db
+            .bones
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| format!("#{}", id))
+    };
+    let channel = |c: &Channel| format!("{} {}.{}", name(c.bone), c.curve, c.axis);
+    let frames = |f: &[u16]| {
+        f.iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut out = String::new();
+    for change in changes {
+        let _ = match change {
+            Change::BoneAdded(id) => writeln!(out, "+ {}", name(*id)),
+            Change::BoneRemoved(id) => writeln!(out, "- {}", name(*id)),
+            Change::AnimChanged(id) => writeln!(out, "~ {}: animation type changed", name(*id)),
+            Change::SetTypeChanged {
+                channel: c,
+                from,
+                to,
+            } => {
+                writeln!(out, "~ {}: {} -> {}", channel(c), from, to)
+            }
+            Change::KeyframesInserted {
+                channel: c,
+                frames: f,
+            } => {
+                writeln!(out, "~ {}: keyframes added at {}", channel(c), frames(f))
+            }
+            Change::KeyframesRemoved {
+                channel: c,
+                frames: f,
+            } => {
+                writeln!(out, "~ {}: keyframes removed at {}", channel(c), frames(f))
+            }
+            Change::Deviation {
+                channel: c,
+                frame,
+                delta,
+            } => {
+                writeln!(out, "~ {}: off by {} at frame {}", channel(c), delta, frame)
+            }
+        };
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn diff_report() {
+        let a = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(Vec3::ZERO))),
+                (1, Some(BoneAnim::Rotation(Vec3::ZERO))),
+            ],
+        };
+        let rot = Vec3 {
+            x: FrameData::Linear(vec![
+                Keyframe {
+                    frame: 0,
+                    value: 0.,
+                },
+                Keyframe {
+                    frame: 4,
+                    value: 1.,
+                },
+            ]),
+            ..Vec3::ZERO
+        };
+        let b = QualifiedMotion {
+            anims: vec![(1, Some(BoneAnim::Rotation(rot))), (3, None)],
+        };
+        let changes = diff(&a, &b, 0.01);
+        assert_eq!(changes.len(), 5);
+        assert_eq!(
+            report(&changes, &mot_db()),
+            "- gblctr\n\
+             ~ kl_kubi rotation.x: pose -> linear\n\
+             ~ kl_kubi rotation.x: keyframes added at 0, 4\n\
+             ~ kl_kubi rotation.x: off by 1 at frame 4\n\
+             + n_hara\n"
+        );
+        assert!(diff(&a, &a, 0.).is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+//! FARC archives, the containers the game ships its files in (`mot_PV001.farc`)
+//!
+//! All the fields are big endian, the header is followed by a table of entries (a null terminated
+//! name, the offset and the size) and the data of every entry, aligned to the archive's alignment.
+//! `FArc` archives store the data as is, `FArC` archives gzip every entry and store both sizes.
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take, take_until};
+use nom::combinator::map;
+use nom::number::complete::be_u32;
+use nom::IResult;
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+const MAGIC_PLAIN: &[u8] = b"FArc";
+const MAGIC_COMPRESSED: &[u8] = b"FArC";
+///Encrypted and Future Tone archives
+const MAGIC_EXTENDED: &[u8] = b"FARC";
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+
+#[derive(Debug)]
+pub enum FarcError {
+    Malformed,
+    ///`FARC` archives aren't supported
+    Unsupported,
+    Decompress(String, io::Error),
+}
+
+impl fmt::Display for FarcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FarcError::Malformed => write!(f, "malformed archive"),
+            FarcError::Unsupported => write!(f, "`FARC` archives aren't supported"),
+            FarcError::Decompress(name, e) => write!(f, "failed to decompress `{}`: {}", name, e),
+        }
+    }
+}
+
+impl std::error::Error for FarcError {}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct FarcEntry {
+    pub name: String,
+    ///Decompressed contents
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Farc {
+    pub alignment: u32,
+    ///Whether the entries are gzipped when written
+    pub compressed: bool,
+    pub entries: Vec<FarcEntry>,
+}
+
+impl Default for Farc {
+    fn default() -> Self {
+        Self {
+            alignment: 16,
+            compressed: true,
+            entries: vec![],
+        }
+    }
+}
+
+//Name, offset, stored size and decompressed size
+type RawEntry<'a> = (&'a [u8], u32, u32, u32);
+
+fn parse_entry(compressed: bool) -> impl Fn(&[u8]) -> IResult<&[u8], RawEntry<'_>> {
+    move |i| {
+        let (i, name) = take_until(&b"\0"[..])(i)?;
+        let (i, _) = take(1usize)(i)?;
+        let (i, offset) = be_u32(i)?;
+        let (i, size) = be_u32(i)?;
+        let (i, original) = if compressed { be_u32(i)? } else { (i, size) };
+        Ok((i, (name, offset, size, original)))
+    }
+}
+
+fn parse_table(i: &[u8]) -> IResult<&[u8], (bool, u32, Vec<RawEntry<'_>>)> {
+    let (i, compressed) = alt((
+        map(tag(MAGIC_PLAIN), |_| false),
+        map(tag(MAGIC_COMPRESSED), |_| true),
+    ))(i)?;
+    let (i, header_size) = be_u32(i)?;
+    //The header size counts the alignment and the table
+    let (rest, header) = take(header_size)(i)?;
+    let (mut table, alignment) = be_u32(header)?;
+    let mut entries = vec![];
+    while !table.is_empty() {
+        let (t, entry) = parse_entry(compressed)(table)?;
+        table = t;
+        entries.push(entry);
+    }
+    Ok((rest, (compressed, alignment, entries)))
+}
+
+fn align(len: usize, alignment: usize) -> usize {
+    len + (alignment - len % alignment) % alignment
+}
+
+impl Farc {
+    ///Reads an archive, the entries of `FArC` archives are decompressed
+    pub fn read(i: &[u8]) -> Result<Self, FarcError> {
+        if i.starts_with(MAGIC_EXTENDED) {
+            return Err(FarcError::Unsupported);
+        }
+        let (_, (compressed, alignment, table)) =
+            parse_table(i).map_err(|_| FarcError::Malformed)?;
+        let mut entries = vec![];
+        for (name, offset, size, _) in table {
+            let name = String::from_utf8_lossy(name).into_owned();
+            let (offset, size) = (offset as usize, size as usize);
+            let stored = i.get(offset..offset + size).ok_or(FarcError::Malformed)?;
+            //Entries which didn't shrink may be stored as is
+            let data = if compressed && stored.starts_with(GZIP_MAGIC) {
+                //The size in the header isn't trusted to reserve memory
+                let mut data = vec![];
+                if let Err(e) = GzDecoder::new(stored).read_to_end(&mut data) {
+                    return Err(FarcError::Decompress(name, e));
+                }
+                data
+            } else {
+                stored.to_vec()
+            };
+            entries.push(FarcEntry { name, data });
+        }
+        Ok(Self {
+            alignment,
+            compressed,
+            entries,
+        })
+    }
+
+    ///Contents of the entry `name`, ignoring case
+    pub fn entry(&self, name: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|x| x.name.eq_ignore_ascii_case(name))
+            .map(|x| &x.data[..])
+    }
+
+    ///Replaces the contents of the entry `name`, adding it when the archive doesn't have it
+    pub fn insert(&mut self, name: &str, data: Vec<u8>) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|x| x.name.eq_ignore_ascii_case(name))
+        {
+            Some(entry) => entry.data = data,
+            None => self.entries.push(FarcEntry {
+                name: name.to_string(),
+                data,
+            }),
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<usize> {
+        let alignment = self.alignment.max(1) as usize;
+        let mut stored = vec![];
+        for entry in &self.entries {
+            if self.compressed {
+                let mut gz = GzEncoder::new(vec![], Compression::default());
+                gz.write_all(&entry.data)?;
+                stored.push(gz.finish()?);
+            } else {
+                stored.push(entry.data.clone());
+            }
+        }
+        let fields = if self.compressed { 12 } else { 8 };
+        let table_size: usize = self.entries.iter().map(|x| x.name.len() + 1 + fields).sum();
+        let header_size = 4 + table_size;
+
+        let mut header = vec![];
+        header.extend_from_slice(if self.compressed {
+            MAGIC_COMPRESSED
+        } else {
+            MAGIC_PLAIN
+        });
+        header.extend_from_slice(&(header_size as u32).to_be_bytes());
+        header.extend_from_slice(&(alignment as u32).to_be_bytes());
+        let mut offset = align(8 + header_size, alignment);
+        for (entry, data) in self.entries.iter().zip(&stored) {
+            header.extend_from_slice(entry.name.as_bytes());
+            header.push(0);
+            header.extend_from_slice(&(offset as u32).to_be_bytes());
+            header.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            if self.compressed {
+                header.extend_from_slice(&(entry.data.len() as u32).to_be_bytes());
+            }
+            offset = align(offset + data.len(), alignment);
+        }
+        header.resize(align(header.len(), alignment), 0);
+        writer.write_all(&header)?;
+        let mut len = header.len();
+        for data in &stored {
+            let padded = align(data.len(), alignment);
+            writer.write_all(data)?;
+            writer.write_all(&vec![0; padded - data.len()])?;
+            len += padded;
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn farc(compressed: bool) -> Farc {
+        Farc {
+            alignment: 16,
+            compressed,
+            entries: vec![
+                FarcEntry {
+                    name: "mot_PV001.bin".into(),
+                    data: (0..100u8).collect(),
+                },
+                FarcEntry {
+                    name: "empty.bin".into(),
+                    data: vec![],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn farc_roundtrip() {
+        for &compressed in &[false, true] {
+            let farc = farc(compressed);
+            let mut out = vec![];
+            let len = farc.write(&mut out).unwrap();
+            assert_eq!(len, out.len());
+            assert_eq!(len % 16, 0);
+            assert_eq!(Farc::read(&out).unwrap(), farc);
+        }
+    }
+
+    #[test]
+    fn farc_entries() {
+        let mut farc = farc(true);
+        assert_eq!(farc.entry("MOT_PV001.BIN").map(|x| x.len()), Some(100));
+        farc.insert("mot_pv001.bin", vec![1, 2]);
+        farc.insert("other.bin", vec![3]);
+        assert_eq!(farc.entries.len(), 3);
+        assert_eq!(farc.entry("mot_PV001.bin"), Some(&[1, 2][..]));
+        assert!(matches!(
+            Farc::read(b"FARC\0\0\0\0"),
+            Err(FarcError::Unsupported)
+        ));
+    }
+}
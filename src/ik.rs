@@ -0,0 +1,472 @@
+use super::interpolate::bake;
+use super::qualified::*;
+use super::retarget::chain_links;
+use super::rotation::{bake_rotation, euler_to_quat};
+
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix4, One, Point3, Quaternion, Rad, Rotation, Rotation3,
+    SquareMatrix, Transform, Vector3, Zero,
+};
+use diva_db::bone::{BoneType, Skeleton};
+use diva_db::mot::MotionSetDatabase;
+
+use std::f32::consts::PI;
+
+///Bones of the IK chains and the FK bones of their first and second link
+const IK_CHAINS: &[(&str, &str, Option<&str>)] = &[
+    ("c_kata_l", "j_kata_l_wj_cu", Some("j_ude_l_wj")),
+    ("c_kata_r", "j_kata_r_wj_cu", Some("j_ude_r_wj")),
+    ("cl_momo_l", "j_momo_l_wj", Some("j_sune_l_wj")),
+    ("cl_momo_r", "j_momo_r_wj", Some("j_sune_r_wj")),
+    ("cl_mune", "j_mune_wj", None),
+    ("cl_kao", "j_kao_wj", None),
+];
+
+//Ids of a chain bone and of its FK bones
+type ChainIds = (usize, usize, Option<usize>);
+
+//`None` when `name` isn't a chain, fails with the first bone missing from `mot_db`
+fn chain_ids(mot_db: &MotionSetDatabase, name: &str) -> Option<Result<ChainIds, String>> {
+    let (chain, root, joint) = IK_CHAINS.iter().find(|(chain, _, _)| *chain == name)?;
+    let id = |name: &str| {
+        mot_db
+            .bones
+            .iter()
+            .position(|x| x[..] == *name)
+            .ok_or_else(|| name.to_string())
+    };
+    let ids = || Ok((id(chain)?, id(root)?, joint.map(id).transpose()?));
+    Some(ids())
+}
+
+///FK rotations of an IK chain, `joint` is the rotation of the second link relative to the first
+#[derive(Debug, PartialEq, Clone)]
+pub struct FkChain {
+    pub root: Vec3,
+    pub joint: Vec3,
+}
+
+fn quat(v: &Vec3, frame: u16) -> Quaternion<f32> {
+    let e = v.interpolate(frame as f32);
+    euler_to_quat(e.x, e.y, e.z)
+}
+
+//Angle between the first link and the target, and bend of the joint
+fn solve_links((a, b): (f32, f32), dist: f32) -> (f32, f32) {
+    let d = dist.clamp((a - b).abs(), a + b).max(1e-6);
+    let angle = |cos: f32| cos.clamp(-1., 1.).acos();
+    let root = angle((a * a + d * d - b * b) / (2. * a * d));
+    let joint = angle((a * a + b * b - d * d) / (2. * a * b));
+    (root, PI - joint)
+}
+
+//Turns `rotation` so that its X axis points at `target`
+fn aim(rotation: Quaternion<f32>, target: Vector3<f32>) -> Quaternion<f32> {
+    if target.magnitude2() == 0. {
+        return rotation;
+    }
+    let axis = rotation.rotate_vector(Vector3::unit_x());
+    Quaternion::between_vectors(axis, target.normalize()) * rotation
+}
+
+//Length of the link of the single link chain `name`, see `bone_positions`
+fn link_length(skel: &Skeleton, name: &str) -> Option<f32> {
+    let index = skel.bones.iter().position(|x| x.name == name)?;
+    let (x, y, z) = *bone_positions(skel)[index].get(1)?;
+    Some((x * x + y * y + z * z).sqrt())
+}
+
+//The skeleton's hierarchy posed by a motion
+//
+//Bones take their offset from `skeleton_nodes`, `Position` curves are added to it and rotations
+//are applied after it. IK chains are solved against their target in the world, or take the
+//rotations of their FK bones when the motion has those instead
+struct Rig<'a> {
+    mot: &'a QualifiedMotion,
+    mot_db: &'a MotionSetDatabase,
+    skel: &'a Skeleton<'a>,
+    nodes: Vec<SkeletonNode>,
+}
+
+impl<'a> Rig<'a> {
+    fn new(mot: &'a QualifiedMotion, mot_db: &'a MotionSetDatabase, skel: &'a Skeleton) -> Self {
+        Rig {
+            mot,
+            mot_db,
+            skel,
+            nodes: skeleton_nodes(skel),
+        }
+    }
+
+    fn node(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|x| x.name == name)
+    }
+
+    fn anim(&self, name: &str) -> Option<&'a BoneAnim> {
+        let id = self.mot_db.bones.iter().position(|x| x[..] == *name)?;
+        let (_, anim) = self.mot.anims.iter().find(|(i, _)| *i == id)?;
+        anim.as_ref()
+    }
+
+    //World transform of the node `i`
+    fn world(&self, i: usize, frame: u16) -> Matrix4<f32> {
+        self.frame(i, frame) * Matrix4::from(self.rotation(i, frame))
+    }
+
+    //World transform of the node `i` before its own rotation is applied
+    fn frame(&self, i: usize, frame: u16) -> Matrix4<f32> {
+        let node = &self.nodes[i];
+        let parent = node
+            .parent
+            .map_or_else(Matrix4::identity, |p| self.world(p, frame));
+        let (x, y, z) = node.offset;
+        let mut offset = Vector3::new(x, y, z);
+        match self.anim(&node.name) {
+            Some(BoneAnim::Position(p)) | Some(BoneAnim::PositionRotation { position: p, .. }) => {
+                offset += p.interpolate(frame as f32)
+            }
+            _ => (),
+        }
+        parent * Matrix4::from_translation(offset)
+    }
+
+    fn rotation(&self, i: usize, frame: u16) -> Quaternion<f32> {
+        let name = &self.nodes[i].name[..];
+        let own = |name: &str| match self.anim(name) {
+            Some(BoneAnim::Rotation(r)) | Some(BoneAnim::PositionRotation { rotation: r, .. }) => {
+                quat(r, frame)
+            }
+            _ => Quaternion::one(),
+        };
+        for &(chain, root, joint) in IK_CHAINS {
+            let solved = || self.solve(chain, frame);
+            match joint {
+                //Single link chains turn their own bone, the others only their links
+                None if name == chain => return solved().map_or_else(|| own(root), |x| x.0),
+                Some(_) if name == chain => return Quaternion::one(),
+                Some(_) if name == root => return solved().map_or_else(|| own(root), |x| x.0),
+                Some(joint) if name == joint => {
+                    return solved().map_or_else(|| own(joint), |x| x.1)
+                }
+                _ => (),
+            }
+        }
+        own(name)
+    }
+
+    //Rotations of both links of `chain` reaching for its target, relative to their parents
+    //
+    //The chain's second curve is its rotation before being aimed at the target, the links point
+    //along X and bend around Z. `None` when the motion doesn't animate `chain` with IK
+    fn solve(&self, chain: &str, frame: u16) -> Option<(Quaternion<f32>, Quaternion<f32>)> {
+        let (target, rotation) = match self.anim(chain)? {
+            BoneAnim::RotationIK { target, rotation } | BoneAnim::ArmIK { target, rotation } => {
+                (target, rotation)
+            }
+            BoneAnim::PositionIKRotation { position, target } => (position, target),
+            _ => return None,
+        };
+        let target = Point3::from_vec(target.interpolate(frame as f32));
+        let frame_world = self.frame(self.node(chain)?, frame);
+        let target = frame_world.invert()?.transform_point(target).to_vec();
+        let rotation = aim(quat(rotation, frame), target);
+        match chain_links(self.skel, chain) {
+            Some(links) => {
+                let (angle, bend) = solve_links(links, target.magnitude());
+                let root = rotation * Quaternion::from_angle_z(Rad(angle));
+                Some((root, Quaternion::from_angle_z(Rad(-bend))))
+            }
+            None => Some((rotation, Quaternion::one())),
+        }
+    }
+
+    //The IK bone of `chain` reaching for the tip of its FK links for frames `0..len`
+    //
+    //The rotation is recovered aimed at the target, inverse of `solve` for rotations already
+    //aimed like it produces. `None` when the FK bones of `chain` aren't all animated
+    fn ik_from_fk(&self, chain: &str, len: u16) -> Option<BoneAnim> {
+        let &(_, root, joint) = IK_CHAINS.iter().find(|x| x.0 == chain)?;
+        let fk = |name: &str| match self.anim(name) {
+            Some(BoneAnim::Rotation(v)) => Some(v),
+            _ => None,
+        };
+        let i = self.node(chain)?;
+        let root = fk(root)?;
+        let joint = match joint {
+            Some(joint) => Some(fk(joint)?),
+            None => None,
+        };
+        let links = chain_links(self.skel, chain);
+        let reach = link_length(self.skel, chain).unwrap_or(1.);
+        let tip = |frame| {
+            let link = match (joint, links) {
+                (Some(joint), Some((a, b))) => {
+                    let joint = quat(joint, frame).rotate_vector(Vector3::unit_x());
+                    Vector3::unit_x() * a + joint * b
+                }
+                _ => Vector3::unit_x() * reach,
+            };
+            quat(root, frame).rotate_vector(link)
+        };
+        let target = bake(len, |frame| {
+            let tip = Point3::from_vec(tip(frame));
+            self.frame(i, frame).transform_point(tip).to_vec()
+        });
+        let rotation = match links {
+            Some(links) if joint.is_some() => bake_rotation(len, Vector3::zero(), |frame| {
+                let (angle, _) = solve_links(links, tip(frame).magnitude());
+                quat(root, frame) * Quaternion::from_angle_z(Rad(-angle))
+            }),
+            _ => root.clone(),
+        };
+        let mode = self.skel.bones.iter().find(|x| x.name == chain)?.mode;
+        Some(match mode {
+            BoneType::Type5 => BoneAnim::ArmIK { target, rotation },
+            BoneType::Type6 => BoneAnim::PositionIKRotation {
+                position: target,
+                target: rotation,
+            },
+            _ => BoneAnim::RotationIK { target, rotation },
+        })
+    }
+}
+
+impl QualifiedMotion {
+    ///FK rotations of every IK chain, solved against the skeleton `skel`
+    ///
+    ///IK targets are positions in the world, chains are placed by their parents in `skel` as
+    ///posed by the motion. Leg IK keeps its ankle target in `position` and its rotation in
+    ///`target`. The links are rotated so their X axis points at the target before bending
+    ///around Z: the twist convention of the game hasn't been confirmed
+    pub fn bake_ik(&self, mot_db: &MotionSetDatabase, skel: &Skeleton) -> Vec<(usize, FkChain)> {
+        let len = self.get_max_keyframe();
+        let rig = Rig::new(self, mot_db, skel);
+        let mut out = vec![];
+        for (name, _, _) in IK_CHAINS {
+            let id = match mot_db.bones.iter().position(|x| x == name) {
+                Some(id) if rig.solve(name, 0).is_some() => id,
+                _ => continue,
+            };
+            let solved: Vec<_> = (0..len)
+                .map(|frame| {
+                    rig.solve(name, frame)
+                        .unwrap_or((Quaternion::one(), Quaternion::one()))
+                })
+                .collect();
+            let root = bake_rotation(len, Vector3::zero(), |frame| solved[frame as usize].0);
+            let joint = bake_rotation(len, Vector3::zero(), |frame| solved[frame as usize].1);
+            out.push((id, FkChain { root, joint }));
+        }
+        out
+    }
+
+    ///Replaces the arm, leg, chest and face IK bones with `Rotation` animations of their FK
+    ///bones, see `bake_ik`
+    ///
+    ///Fails with the name of the first FK bone missing from `mot_db`, leaving the motion as is.
+    ///`to_ik` reverses it
+    pub fn to_fk(&mut self, mot_db: &MotionSetDatabase, skel: &Skeleton) -> Result<(), String> {
+        let mut chains = vec![];
+        for (id, chain) in self.bake_ik(mot_db, skel) {
+            if let Some(ids) = chain_ids(mot_db, &mot_db.bones[id]) {
+                chains.push((ids?, chain));
+            }
+        }
+        for ((id, root, joint), chain) in chains {
+            self.anims
+                .retain(|(i, _)| *i != id && *i != root && Some(*i) != joint);
+            self.anims
+                .push((root, Some(BoneAnim::Rotation(chain.root))));
+            if let Some(joint) = joint {
+                self.anims
+                    .push((joint, Some(BoneAnim::Rotation(chain.joint))));
+            }
+        }
+        self.sort(mot_db);
+        Ok(())
+    }
+
+    ///Rebuilds the IK bones from the FK bones written by `to_fk`
+    ///
+    ///Targets are placed in the world at the tip of the chains posed on `skel`, the rotations
+    ///are recovered aimed at them. Chains whose FK bones aren't all animated are left alone
+    pub fn to_ik(&mut self, mot_db: &MotionSetDatabase, skel: &Skeleton) {
+        let len = self.get_max_keyframe();
+        let rig = Rig::new(self, mot_db, skel);
+        let mut chains = vec![];
+        for (name, _, _) in IK_CHAINS {
+            let ids = match chain_ids(mot_db, name) {
+                Some(Ok(ids)) => ids,
+                _ => continue,
+            };
+            if let Some(anim) = rig.ik_from_fk(name, len) {
+                chains.push((ids, anim));
+            }
+        }
+        for ((id, root, joint), anim) in chains {
+            self.anims.retain(|(i, _)| *i != root && Some(*i) != joint);
+            self.anims.push((id, Some(anim)));
+        }
+        self.sort(mot_db);
+    }
+
+    ///Moves the IK targets of a motion made for the skeleton `from` to where the same FK poses
+    ///reach on `to`
+    ///
+    ///Chains already animated through their FK bones are left alone. Fails like `to_fk`
+    pub(crate) fn resolve_ik(
+        &mut self,
+        mot_db: &MotionSetDatabase,
+        from: &Skeleton,
+        to: &Skeleton,
+    ) -> Result<(), String> {
+        let mut fk = self.clone();
+        fk.to_fk(mot_db, from)?;
+        let len = fk.get_max_keyframe();
+        let rig = Rig::new(&fk, mot_db, to);
+        for (id, anim) in self.anims.iter_mut() {
+            let ik = matches!(
+                anim,
+                Some(BoneAnim::RotationIK { .. })
+                    | Some(BoneAnim::ArmIK { .. })
+                    | Some(BoneAnim::PositionIKRotation { .. })
+            );
+            if let Some(solved) = rig.ik_from_fk(&mot_db.bones[*id], len).filter(|_| ik) {
+                *anim = Some(solved);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use crate::FrameData;
+
+    fn pose(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3 {
+            x: FrameData::Pose(x),
+            y: FrameData::Pose(y),
+            z: FrameData::Pose(z),
+        }
+    }
+
+    fn origin(m: Matrix4<f32>) -> Vector3<f32> {
+        m.transform_point(Point3::origin()).to_vec()
+    }
+
+    #[test]
+    fn fk_roundtrip() {
+        let (mot_db, bone_db) = (mot_db(), bone_db());
+        let skel = &bone_db.skeletons[0];
+        let rest = QualifiedMotion { anims: vec![] };
+        let rig = Rig::new(&rest, &mot_db, skel);
+        let at = |name: &str, x, y, z| {
+            let target = origin(rig.world(rig.node(name).unwrap(), 0)) + Vector3::new(x, y, z);
+            pose(target.x, target.y, target.z)
+        };
+        //Rotations already aimed at targets in reach are recovered as is
+        let arm = BoneAnim::ArmIK {
+            target: at("c_kata_l", 0.3, 0., 0.),
+            rotation: pose(0.4, 0., 0.),
+        };
+        let reach = link_length(skel, "cl_kao").unwrap();
+        let kao = BoneAnim::RotationIK {
+            target: at("cl_kao", 0., reach, 0.),
+            rotation: pose(0., 0., PI / 2.),
+        };
+        let mot = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(Vec3::ZERO))),
+                (bone_id("cl_kao"), Some(kao)),
+                (bone_id("c_kata_l"), Some(arm)),
+            ],
+        };
+
+        let mut fk = mot.clone();
+        fk.to_fk(&mot_db, skel).unwrap();
+        let ids: Vec<usize> = fk.anims.iter().map(|(id, _)| *id).collect();
+        let fk_ids = ["gblctr", "j_kata_l_wj_cu", "j_ude_l_wj", "j_kao_wj"];
+        assert_eq!(ids.len(), fk_ids.len());
+        assert!(fk_ids.iter().all(|x| ids.contains(&bone_id(x))));
+        assert!(fk
+            .anims
+            .iter()
+            .skip(1)
+            .all(|(_, a)| matches!(a, Some(BoneAnim::Rotation(_)))));
+        //The face is turned up to its target
+        match fk.anims.iter().find(|(id, _)| *id == bone_id("j_kao_wj")) {
+            Some((_, Some(BoneAnim::Rotation(v)))) => {
+                assert!((v.z.interpolate(0.) - PI / 2.).abs() < 1e-4)
+            }
+            e => panic!("unexpected anim {:?}", e),
+        }
+
+        fk.to_ik(&mot_db, skel);
+        assert_eq!(fk.anims.len(), mot.anims.len());
+        for ((id, a), (back_id, b)) in mot.anims.iter().zip(&fk.anims) {
+            assert_eq!(id, back_id);
+            let (a, b) = (a.as_ref().unwrap().curves(), b.as_ref().unwrap().curves());
+            for ((name, a), (_, b)) in a.iter().zip(&b) {
+                match *name {
+                    "rotation" => assert!(quat(a, 0).dot(quat(b, 0)).abs() > 1. - 1e-4),
+                    _ => assert!((a.interpolate(0.) - b.interpolate(0.)).magnitude() < 1e-4),
+                }
+            }
+        }
+
+        let mut missing = mot;
+        let db = MotionSetDatabase {
+            bones: mot_db.bones[..10].to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(missing.to_fk(&db, skel), Err("j_kata_l_wj_cu".into()));
+    }
+
+    #[test]
+    fn leg_ik_game_pose() {
+        let (mot_db, bone_db) = (mot_db(), bone_db());
+        let skel = &bone_db.skeletons[0];
+        //Frame 0 of mot_PV001.bin, from the root of the skeleton down to the left leg
+        let rotation = |x, y, z| Some(BoneAnim::Rotation(pose(x, y, z)));
+        let ankle = Vector3::new(0.1389229, 0.105, -0.7294785);
+        let leg = BoneAnim::PositionIKRotation {
+            position: pose(ankle.x, ankle.y, ankle.z),
+            target: pose(-0.1404609, -0.1176496, 0.4656039),
+        };
+        let root = BoneAnim::PositionRotation {
+            position: pose(0., 1.051729, -0.669865),
+            rotation: Vec3::ZERO,
+        };
+        let mut mot = QualifiedMotion {
+            anims: vec![
+                (bone_id("n_hara_cp"), Some(root)),
+                (bone_id("kg_hara_y"), rotation(0., 0.07592517, 0.)),
+                (
+                    bone_id("kl_hara_xz"),
+                    rotation(-0.001819399, 0., -0.00273026),
+                ),
+                (bone_id("n_hara"), rotation(0., 1.570796, 0.)),
+                (bone_id("kl_kosi_y"), rotation(0., -0.04882688, 0.)),
+                (
+                    bone_id("kl_kosi_xz"),
+                    rotation(0.007234629, -0.01577253, -0.1310703),
+                ),
+                (bone_id("cl_momo_l"), Some(leg)),
+            ],
+        };
+        let hip = Vector3::new(0.0635304, 0.8964555, -0.6962284);
+        let rig = Rig::new(&mot, &mot_db, skel);
+        let node = |rig: &Rig, name| rig.world(rig.node(name).unwrap(), 0);
+        assert!((origin(node(&rig, "cl_momo_l")) - hip).magnitude() < 1e-4);
+
+        mot.to_fk(&mot_db, skel).unwrap();
+        assert!(mot.anims.iter().all(|(id, _)| *id != bone_id("cl_momo_l")));
+        let rig = Rig::new(&mot, &mot_db, skel);
+        assert!((origin(node(&rig, "kl_asi_l_wj_co")) - ankle).magnitude() < 1e-3);
+        let knee = origin(node(&rig, "j_sune_l_wj"));
+        assert!(((knee - hip).magnitude() - 0.39).abs() < 1e-3);
+    }
+}
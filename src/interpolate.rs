@@ -0,0 +1,212 @@
+use super::qualified::Vec3;
+use super::*;
+
+use cgmath::Vector3;
+
+impl FrameData {
+    ///Evaluates the set at `frame`
+    ///
+    ///Frames outside of the keyframe range are clamped to the first/last keyframe
+    pub fn interpolate(&self, frame: f32) -> f32 {
+        use FrameData::*;
+        match self {
+            None => 0.,
+            Pose(p) => *p,
+            Linear(l) => interpolate_linear(l, frame),
+            Smooth(l) => interpolate_smooth(l, frame),
+        }
+    }
+
+    ///Frames which have a keyframe in this set
+    pub fn keyframes(&self) -> Vec<u16> {
+        use FrameData::*;
+        match self {
+            None | Pose(_) => vec![],
+            Linear(l) => l.iter().map(|x| x.frame).collect(),
+            Smooth(l) => l.iter().map(|x| x.keyframe.frame).collect(),
+        }
+    }
+
+    ///Slope per frame arriving at and leaving `frame`
+    ///
+    ///Both slopes are equal everywhere except on the keyframes of a `Linear` set
+    pub fn tangents(&self, frame: f32) -> (f32, f32) {
+        use FrameData::*;
+        match self {
+            None | Pose(_) => (0., 0.),
+            Linear(l) => (
+                linear_slope(l, |k| k.frame as f32 >= frame),
+                linear_slope(l, |k| k.frame as f32 > frame),
+            ),
+            Smooth(l) => {
+                let slope = smooth_slope(l, frame);
+                (slope, slope)
+            }
+        }
+    }
+}
+
+//Slope of the segment ending on the first keyframe matching `end`
+fn linear_slope<F: Fn(&Keyframe) -> bool>(keys: &[Keyframe], end: F) -> f32 {
+    match keys.iter().position(end) {
+        Some(n) if n > 0 => {
+            let (k0, k1) = (&keys[n - 1], &keys[n]);
+            (k1.value - k0.value) / (k1.frame - k0.frame) as f32
+        }
+        _ => 0.,
+    }
+}
+
+fn smooth_slope(keys: &[InterpKeyframe], frame: f32) -> f32 {
+    if let Some(k) = keys.iter().find(|x| x.keyframe.frame as f32 == frame) {
+        return k.interpolation;
+    }
+    match keys.iter().position(|x| x.keyframe.frame as f32 > frame) {
+        Some(n) if n > 0 => hermite_slope(&keys[n - 1], &keys[n], frame),
+        _ => 0.,
+    }
+}
+
+fn interpolate_linear(keys: &[Keyframe], frame: f32) -> f32 {
+    let next = keys.iter().position(|x| x.frame as f32 > frame);
+    match next {
+        _ if keys.is_empty() => 0.,
+        Some(0) => keys[0].value,
+        None => keys[keys.len() - 1].value,
+        Some(n) => {
+            let (k0, k1) = (&keys[n - 1], &keys[n]);
+            let t = (frame - k0.frame as f32) / (k1.frame - k0.frame) as f32;
+            k0.value + (k1.value - k0.value) * t
+        }
+    }
+}
+
+fn interpolate_smooth(keys: &[InterpKeyframe], frame: f32) -> f32 {
+    let next = keys.iter().position(|x| x.keyframe.frame as f32 > frame);
+    match next {
+        _ if keys.is_empty() => 0.,
+        Some(0) => keys[0].keyframe.value,
+        None => keys[keys.len() - 1].keyframe.value,
+        Some(n) => hermite(&keys[n - 1], &keys[n], frame),
+    }
+}
+
+//Tangents are stored as value per frame, which is why they're scaled by the segment length
+pub(crate) fn hermite(k0: &InterpKeyframe, k1: &InterpKeyframe, frame: f32) -> f32 {
+    let len = (k1.keyframe.frame - k0.keyframe.frame) as f32;
+    let t = (frame - k0.keyframe.frame as f32) / len;
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let h00 = 2. * t3 - 3. * t2 + 1.;
+    let h01 = -2. * t3 + 3. * t2;
+    let h10 = t3 - 2. * t2 + t;
+    let h11 = t3 - t2;
+    h00 * k0.keyframe.value
+        + h01 * k1.keyframe.value
+        + (h10 * k0.interpolation + h11 * k1.interpolation) * len
+}
+
+pub(crate) fn hermite_slope(k0: &InterpKeyframe, k1: &InterpKeyframe, frame: f32) -> f32 {
+    let len = (k1.keyframe.frame - k0.keyframe.frame) as f32;
+    let t = (frame - k0.keyframe.frame as f32) / len;
+    let t2 = t * t;
+    let d00 = 6. * t2 - 6. * t;
+    let d01 = -6. * t2 + 6. * t;
+    let d10 = 3. * t2 - 4. * t + 1.;
+    let d11 = 3. * t2 - 2. * t;
+    (d00 * k0.keyframe.value + d01 * k1.keyframe.value) / len
+        + d10 * k0.interpolation
+        + d11 * k1.interpolation
+}
+
+impl Vec3 {
+    pub fn interpolate(&self, frame: f32) -> Vector3<f32> {
+        Vector3::new(
+            self.x.interpolate(frame),
+            self.y.interpolate(frame),
+            self.z.interpolate(frame),
+        )
+    }
+
+    ///Incoming and outgoing slopes of all 3 components
+    pub fn tangents(&self, frame: f32) -> (Vector3<f32>, Vector3<f32>) {
+        let (xi, xo) = self.x.tangents(frame);
+        let (yi, yo) = self.y.tangents(frame);
+        let (zi, zo) = self.z.tangents(frame);
+        (Vector3::new(xi, yi, zi), Vector3::new(xo, yo, zo))
+    }
+
+    ///Sorted and deduplicated keyframes of all 3 components
+    pub fn keyframes(&self) -> Vec<u16> {
+        let mut frames = self.x.keyframes();
+        frames.append(&mut self.y.keyframes());
+        frames.append(&mut self.z.keyframes());
+        frames.sort();
+        frames.dedup();
+        frames
+    }
+}
+
+///Baked curves are simplified with this tolerance to drop redundant keyframes
+const BAKE_TOLERANCE: f32 = 1e-4;
+
+///Samples `f` on frames `0..len` into `Linear` curves, then drops the redundant keyframes
+pub(crate) fn bake<F: FnMut(u16) -> Vector3<f32>>(len: u16, mut f: F) -> Vec3 {
+    let mut keys = [vec![], vec![], vec![]];
+    for frame in 0..len {
+        let v = f(frame);
+        for (keys, value) in keys.iter_mut().zip(&[v.x, v.y, v.z]) {
+            keys.push(Keyframe {
+                frame,
+                value: *value,
+            });
+        }
+    }
+    let [x, y, z] = keys;
+    let mut vec = Vec3 {
+        x: FrameData::Linear(x),
+        y: FrameData::Linear(y),
+        z: FrameData::Linear(z),
+    };
+    vec.simplify(BAKE_TOLERANCE);
+    vec
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(frame: u16, value: f32, interpolation: f32) -> InterpKeyframe {
+        InterpKeyframe {
+            keyframe: Keyframe { frame, value },
+            interpolation,
+        }
+    }
+
+    #[test]
+    fn linear_interpolate() {
+        let set = FrameData::Linear(vec![
+            Keyframe {
+                frame: 0,
+                value: 0.,
+            },
+            Keyframe {
+                frame: 10,
+                value: 1.,
+            },
+        ]);
+        assert_eq!(set.interpolate(5.), 0.5);
+        assert_eq!(set.interpolate(-3.), 0.);
+        assert_eq!(set.interpolate(20.), 1.);
+    }
+
+    #[test]
+    fn smooth_interpolate() {
+        let flat = FrameData::Smooth(vec![key(0, 0., 0.), key(10, 1., 0.)]);
+        assert_eq!(flat.interpolate(5.), 0.5);
+        assert_eq!(flat.interpolate(10.), 1.);
+        //A constant slope should be reproduced exactly
+        let slope = FrameData::Smooth(vec![key(0, 0., 0.1), key(10, 1., 0.1)]);
+        assert!((slope.interpolate(2.5) - 0.25).abs() < 1e-6);
+    }
+}
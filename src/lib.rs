@@ -1,15 +1,46 @@
 #![feature(seek_convenience)]
+mod blend;
 pub mod const_table;
+mod concat;
+pub mod database;
+pub mod diff;
+pub mod farc;
+mod ik;
+mod interpolate;
+mod looping;
+mod mirror;
 pub mod qualified;
 pub mod read;
+mod retarget;
+mod simplify;
+pub mod stats;
+pub mod rotation;
+pub mod text;
+mod time;
+pub mod validate;
 mod write_alt;
 
+#[cfg(test)]
+mod test_util;
+
+pub use concat::ConcatError;
+pub use ik::FkChain;
+pub use time::TimeScaleError;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+///Frame rate of the game, keyframes are in frames at this rate
+pub const FPS: f32 = 60.;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Motion {
     pub sets: Vec<FrameData>,
     pub bones: Vec<usize>,
 }
 
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum FrameData {
     None,
     Pose(f32),
@@ -18,13 +49,16 @@ pub enum FrameData {
 }
 
 #[derive(Debug, Default, PartialEq, PartialOrd, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Keyframe {
     pub frame: u16,
     pub value: f32,
 }
 
 #[derive(Debug, Default, PartialEq, PartialOrd, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InterpKeyframe {
+    #[cfg_attr(feature = "serde", serde(flatten))]
     pub keyframe: Keyframe,
     pub interpolation: f32,
 }
@@ -0,0 +1,153 @@
+use super::concat::cross_fade;
+use super::qualified::*;
+use super::rotation::unwrap_angle;
+use super::*;
+
+impl FrameData {
+    //Difference between the last and first frame, rotations are compared modulo a whole turn
+    fn loop_delta(&self, end: u16, angle: bool) -> f32 {
+        let (first, last) = (self.interpolate(0.), self.interpolate(end as f32));
+        if angle {
+            last - unwrap_angle(last, first)
+        } else {
+            last - first
+        }
+    }
+
+    ///Bends the last `blend` frames before `end` so that `end` matches the first frame
+    fn make_loopable(&mut self, end: u16, blend: u16, angle: bool) {
+        use FrameData::*;
+        let delta = self.loop_delta(end, angle);
+        if delta == 0. {
+            return;
+        }
+        let blend = blend.clamp(1, end.max(1));
+        let start = end - blend;
+        let window: Vec<InterpKeyframe> = (start..=end)
+            .map(|frame| {
+                let (w, dw) = cross_fade(frame - start, blend);
+                InterpKeyframe {
+                    keyframe: Keyframe {
+                        frame,
+                        value: self.interpolate(frame as f32) - delta * w,
+                    },
+                    interpolation: self.tangents(frame as f32).1 - delta * dw,
+                }
+            })
+            .collect();
+        match self {
+            None | Pose(_) => (),
+            Linear(l) => {
+                l.retain(|k| k.frame < start);
+                l.extend(window.into_iter().map(|k| k.keyframe));
+            }
+            Smooth(l) => {
+                l.retain(|k| k.keyframe.frame < start);
+                l.extend(window);
+            }
+        }
+    }
+}
+
+impl QualifiedMotion {
+    ///Makes the last frame of every curve match its first one so the motion loops seamlessly
+    ///
+    ///The difference is faded in over the last `blend_frames` frames, which also removes the
+    ///drift of root positions. Rotations only need to match up to a whole turn
+    pub fn make_loopable(&mut self, blend_frames: u16) {
+        let end = self.get_max_keyframe().saturating_sub(1);
+        for (_, anim) in self.anims.iter_mut() {
+            let anim = match anim {
+                Some(a) => a,
+                None => continue,
+            };
+            for (name, v) in anim.curves_mut() {
+                for set in v.sets_mut().iter_mut() {
+                    set.make_loopable(end, blend_frames, name == "rotation");
+                }
+            }
+        }
+    }
+
+    ///Largest jump of each animated bone when the motion loops back to its first frame
+    pub fn loop_discontinuities(&self) -> Vec<(usize, f32)> {
+        let end = self.get_max_keyframe().saturating_sub(1);
+        let mut out = vec![];
+        for (id, anim) in &self.anims {
+            let anim = match anim {
+                Some(a) => a,
+                None => continue,
+            };
+            let jump = anim
+                .curves()
+                .into_iter()
+                .flat_map(|(name, v)| {
+                    let angle = name == "rotation";
+                    v.sets()
+                        .iter()
+                        .map(|set| set.loop_delta(end, angle).abs())
+                        .collect::<Vec<_>>()
+                })
+                .fold(0., f32::max);
+            out.push((*id, jump));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::f32::consts::PI;
+
+    fn linear(keys: &[(u16, f32)]) -> FrameData {
+        FrameData::Linear(
+            keys.iter()
+                .map(|&(frame, value)| Keyframe { frame, value })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn loopable_root() {
+        let pos = Vec3 {
+            x: linear(&[(0, 0.), (20, 2.)]),
+            ..Vec3::ZERO
+        };
+        let rot = Vec3 {
+            z: linear(&[(0, -PI), (20, PI)]),
+            ..Vec3::ZERO
+        };
+        let mut mot = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(pos))),
+                (1, Some(BoneAnim::Rotation(rot))),
+                (2, None),
+            ],
+        };
+        let jumps = mot.loop_discontinuities();
+        assert_eq!(jumps[0], (0, 2.));
+        assert!(jumps[1].1 < 1e-5);
+        assert_eq!(jumps.len(), 2);
+
+        mot.make_loopable(10);
+        assert!(mot.loop_discontinuities()[0].1 < 1e-5);
+        match &mot.anims[0].1 {
+            Some(BoneAnim::Position(v)) => {
+                assert_eq!(v.x.interpolate(5.), 0.5);
+                assert_eq!(v.x.interpolate(20.), 0.);
+            }
+            e => panic!("unexpected anim {:?}", e),
+        }
+    }
+
+    #[test]
+    fn empty_motion() {
+        let mut mot = QualifiedMotion {
+            anims: vec![(0, None)],
+        };
+        mot.make_loopable(4);
+        assert!(mot.loop_discontinuities().is_empty());
+    }
+}
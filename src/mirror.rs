@@ -0,0 +1,157 @@
+use super::qualified::*;
+use super::*;
+
+use diva_db::mot::MotionSetDatabase;
+
+///Swaps the `l` and `r` parts of a bone name, `None` if it has no side
+fn mirror_name(name: &str) -> Option<String> {
+    let mut sided = false;
+    let parts: Vec<&str> = name
+        .split('_')
+        .map(|x| match x {
+            "l" => {
+                sided = true;
+                "r"
+            }
+            "r" => {
+                sided = true;
+                "l"
+            }
+            x => x,
+        })
+        .collect();
+    if sided {
+        Some(parts.join("_"))
+    } else {
+        None
+    }
+}
+
+impl FrameData {
+    fn negate(&mut self) {
+        use FrameData::*;
+        match self {
+            None => (),
+            Pose(p) => *p = -*p,
+            Linear(l) => l.iter_mut().for_each(|k| k.value = -k.value),
+            Smooth(l) => {
+                for k in l {
+                    k.keyframe.value = -k.keyframe.value;
+                    k.interpolation = -k.interpolation;
+                }
+            }
+        }
+    }
+}
+
+impl BoneAnim {
+    ///Mirrors the bone along the X axis
+    fn mirror(&mut self) {
+        for (name, v) in self.curves_mut() {
+            match name {
+                //Mirroring a rotation flips the other two axes
+                "rotation" => {
+                    v.y.negate();
+                    v.z.negate();
+                }
+                "position" | "target" => v.x.negate(),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl QualifiedMotion {
+    ///Mirrors the motion left to right
+    ///
+    ///Bones with an `l`/`r` name part swap their animations with the other side's bone from
+    ///`mot_db`, and every position, IK target and rotation is flipped along the X axis.
+    ///The bones are sorted again afterwards so the motion can be written as is.
+    ///This assumes the two sides of the skeleton have mirrored local axes
+    pub fn mirror(&mut self, mot_db: &MotionSetDatabase) {
+        let bones = &mot_db.bones;
+        for (id, anim) in self.anims.iter_mut() {
+            let pair = mirror_name(&bones[*id]).and_then(|n| bones.iter().position(|x| *x == n));
+            if let Some(pair) = pair {
+                *id = pair;
+            }
+            if let Some(anim) = anim {
+                anim.mirror();
+            }
+        }
+        self.sort(mot_db);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    use nom::number::Endianness;
+
+    #[test]
+    fn mirror_names() {
+        assert_eq!(mirror_name("kl_eye_l_wj").as_deref(), Some("kl_eye_r_wj"));
+        assert_eq!(mirror_name("cl_momo_r").as_deref(), Some("cl_momo_l"));
+        assert_eq!(mirror_name("kl_kubi"), None);
+    }
+
+    #[test]
+    fn mirror_swaps_sides() {
+        let (left, right) = (bone_id("cl_momo_l"), bone_id("cl_momo_r"));
+        let rot = Vec3 {
+            x: FrameData::Pose(1.),
+            y: FrameData::Pose(2.),
+            z: FrameData::Pose(3.),
+        };
+        let mut mot = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(rot.clone()))),
+                (left, Some(BoneAnim::Rotation(rot))),
+                (right, None),
+            ],
+        };
+        mot.mirror(&mot_db());
+        let expected = |x, y, z| Vec3 {
+            x: FrameData::Pose(x),
+            y: FrameData::Pose(y),
+            z: FrameData::Pose(z),
+        };
+        assert_eq!(
+            mot.anims,
+            vec![
+                (0, Some(BoneAnim::Position(expected(-1., 2., 3.)))),
+                (left, None),
+                (right, Some(BoneAnim::Rotation(expected(1., -2., -3.)))),
+            ]
+        );
+    }
+
+    #[test]
+    fn mirror_write_read() {
+        let mot_db = mot_db();
+        let pose = |x| Vec3 {
+            x: FrameData::Pose(x),
+            ..Vec3::ZERO
+        };
+        let arm = |x| {
+            Some(BoneAnim::ArmIK {
+                target: pose(x),
+                rotation: pose(x),
+            })
+        };
+        let mut mot = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(pose(1.)))),
+                (bone_id("c_kata_l"), arm(1.)),
+                (bone_id("c_kata_r"), arm(2.)),
+            ],
+        };
+        mot.mirror(&mot_db);
+        let mut out = vec![];
+        Motion::write_set(&[mot.to_motion()], &mut out).unwrap();
+        let (_, mut read) = Motion::parse_set(&out, Endianness::Little).unwrap();
+        assert_eq!(read.remove(0).qualify(&mot_db, &bone_db()), mot);
+    }
+}
@@ -1,13 +1,21 @@
 use super::*;
 
+mod csv;
+mod gltf;
+mod vmd;
 mod write;
 
+pub use csv::CsvOptions;
+pub use vmd::vmd_bone_names;
+
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct QualifiedMotion {
     pub anims: Vec<(usize, Option<BoneAnim>)>,
 }
 
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Vec3 {
     pub x: FrameData,
     pub y: FrameData,
@@ -45,6 +53,7 @@ impl Vec3 {
 }
 
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum BoneAnim {
     Rotation(Vec3),
     Type1(Vec3, Vec3), //unknown
@@ -72,10 +81,158 @@ pub enum BoneAnim {
     },
 }
 
+impl BoneAnim {
+    ///The (position, rotation) curves of the bone, IK targets are left out
+    pub fn transform(&self) -> (Option<&Vec3>, Option<&Vec3>) {
+        use BoneAnim::*;
+        match self {
+            Rotation(rot) => (None, Some(rot)),
+            Type1(_, _) => (None, None),
+            Position(pos) => (Some(pos), None),
+            PositionRotation { position, rotation } => (Some(position), Some(rotation)),
+            RotationIK { rotation, .. } => (None, Some(rotation)),
+            ArmIK { rotation, .. } => (None, Some(rotation)),
+            PositionIKRotation { position, .. } => (Some(position), None),
+        }
+    }
+
+    ///Curves of the bone by name, in the order their sets are stored
+    pub fn curves(&self) -> Vec<(&'static str, &Vec3)> {
+        use BoneAnim::*;
+        match self {
+            Rotation(v) => vec![("rotation", v)],
+            Type1(v0, v1) => vec![("unk0", v0), ("unk1", v1)],
+            Position(v) => vec![("position", v)],
+            PositionRotation { position, rotation } => {
+                vec![("position", position), ("rotation", rotation)]
+            }
+            RotationIK { target, rotation } => vec![("target", target), ("rotation", rotation)],
+            ArmIK { target, rotation } => vec![("target", target), ("rotation", rotation)],
+            PositionIKRotation { position, target } => {
+                vec![("position", position), ("target", target)]
+            }
+        }
+    }
+
+    pub fn curves_mut(&mut self) -> Vec<(&'static str, &mut Vec3)> {
+        use BoneAnim::*;
+        match self {
+            Rotation(v) => vec![("rotation", v)],
+            Type1(v0, v1) => vec![("unk0", v0), ("unk1", v1)],
+            Position(v) => vec![("position", v)],
+            PositionRotation { position, rotation } => {
+                vec![("position", position), ("rotation", rotation)]
+            }
+            RotationIK { target, rotation } => vec![("target", target), ("rotation", rotation)],
+            ArmIK { target, rotation } => vec![("target", target), ("rotation", rotation)],
+            PositionIKRotation { position, target } => {
+                vec![("position", position), ("target", target)]
+            }
+        }
+    }
+}
+
+impl Vec3 {
+    pub fn sets(&self) -> [&FrameData; 3] {
+        [&self.x, &self.y, &self.z]
+    }
+
+    pub fn sets_mut(&mut self) -> [&mut FrameData; 3] {
+        [&mut self.x, &mut self.y, &mut self.z]
+    }
+}
+
 use diva_db::bone::*;
 use diva_db::mot::*;
 use std::collections::VecDeque;
 
+///Finds the skeleton bone driven by the motion bone `name`
+///
+///`gblctr` and `kg_ya_ex` are animated by motions but aren't part of the skeleton
+pub(crate) fn find_bone<'a>(name: &str, bone_db: &BoneDatabase<'a>) -> Option<Bone> {
+    let bone = bone_db
+        .skeletons
+        .first()?
+        .bones
+        .iter()
+        .find(|x| &x.name[..] == name);
+    match bone {
+        Some(b) => Some(b.clone()),
+        None if name == "gblctr" => Some(Bone {
+            mode: BoneType::Position,
+            ..Default::default()
+        }),
+        None if name == "kg_ya_ex" => Some(Bone {
+            mode: BoneType::Rotation,
+            ..Default::default()
+        }),
+        None => None,
+    }
+}
+
+///Positions of every bone of `skel`, in bone order
+///
+///Each bone has its offset from its parent, `Type4` bones add the length of their link and the
+///`Type5` and `Type6` chains the lengths of both of theirs
+pub(crate) fn bone_positions<'s>(skel: &'s Skeleton) -> Vec<&'s [(f32, f32, f32)]> {
+    let mut index = 0;
+    let mut positions = vec![];
+    for bone in &skel.bones {
+        let count = match bone.mode {
+            BoneType::Type4 => 2,
+            BoneType::Type5 | BoneType::Type6 => 3,
+            _ => 1,
+        };
+        positions.push(skel.positions.get(index..index + count).unwrap_or_default());
+        index += count;
+    }
+    positions
+}
+
+///A bone of the skeleton's hierarchy, see `skeleton_nodes`
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct SkeletonNode {
+    pub name: String,
+    pub parent: Option<usize>,
+    ///Offset from the parent
+    pub offset: (f32, f32, f32),
+}
+
+///The hierarchy of `skel`, which is what the bones' `parent` indices point into
+///
+///The `Type5` and `Type6` bones are followed by both links of their chain, named after the bones
+///following them in `motion_bone_names` (`c_kata_l` is followed by `j_kata_l_wj_cu` and
+///`j_ude_l_wj`). The first link sits on the chain's bone, the second one at the end of the first
+pub(crate) fn skeleton_nodes(skel: &Skeleton) -> Vec<SkeletonNode> {
+    let positions = bone_positions(skel);
+    let mut nodes = vec![];
+    for (bone, positions) in skel.bones.iter().zip(positions) {
+        let node = |name: String, parent, offset: Option<&(f32, f32, f32)>| SkeletonNode {
+            name,
+            parent,
+            offset: offset.copied().unwrap_or_default(),
+        };
+        let parent = bone.parent.map(|x| x as usize);
+        nodes.push(node(bone.name.clone(), parent, positions.first()));
+        if let BoneType::Type5 | BoneType::Type6 = bone.mode {
+            let names = &skel.motion_bone_names;
+            let index = names.iter().position(|x| *x == bone.name);
+            let link = |i: usize| match index.and_then(|x| names.get(x + i)) {
+                Some(name) => name.to_string(),
+                None => format!("{}_link{}", bone.name, i),
+            };
+            let chain = nodes.len() - 1;
+            nodes.push(node(link(1), Some(chain), None));
+            nodes.push(node(link(2), Some(chain + 1), positions.get(1)));
+        }
+    }
+    //Parents are always listed before their children
+    for (i, node) in nodes.iter_mut().enumerate() {
+        node.parent = node.parent.filter(|&p| p < i);
+    }
+    nodes
+}
+
 impl Motion {
     pub fn qualify<'a>(
         self,
@@ -83,7 +240,6 @@ impl Motion {
         bone_db: &BoneDatabase<'a>,
     ) -> QualifiedMotion {
         let mut sets: VecDeque<FrameData> = self.sets.into();
-        let bones = &bone_db.skeletons[0].bones;
         let mut vec3 = || {
             let x = sets.pop_front().unwrap();
             let y = sets.pop_front().unwrap();
@@ -93,20 +249,8 @@ impl Motion {
         let mut anims = vec![];
         for id in self.bones {
             let name = &mot_db.bones[id];
-            let bone = bone_db.skeletons[0]
-                .bones
-                .iter()
-                .find(|x| &x.name[..] == name);
-            let bone = match bone {
-                Some(b) => b.clone(),
-                None if name == "gblctr" => Bone {
-                    mode: BoneType::Position,
-                    ..Default::default()
-                },
-                None if name == "kg_ya_ex" => Bone {
-                    mode: BoneType::Rotation,
-                    ..Default::default()
-                },
+            let bone = match find_bone(name, bone_db) {
+                Some(b) => b,
                 None => {
                     anims.push((id, None));
                     continue;
@@ -117,10 +261,7 @@ impl Motion {
                 id,
                 Some(match mode {
                     BoneType::Rotation => BoneAnim::Rotation(vec3()),
-                    BoneType::Type1 => {
-                        println!("Weird stuff inbound, encoutered TYPE1");
-                        BoneAnim::Type1(vec3(), vec3())
-                    }
+                    BoneType::Type1 => BoneAnim::Type1(vec3(), vec3()),
                     BoneType::Position => BoneAnim::Position(vec3()),
                     BoneType::Type3 => BoneAnim::PositionRotation {
                         position: vec3(),
@@ -141,23 +282,136 @@ impl Motion {
                 }),
             ))
         }
-        println!("{} set(s) are left", sets.len());
         QualifiedMotion { anims }
     }
 }
 
 impl QualifiedMotion {
+    ///Turns the motion back into the game's layout, the sets of every bone one after the other
+    pub fn to_motion(&self) -> Motion {
+        let mut sets = vec![];
+        let mut bones = vec![];
+        for (id, anim) in &self.anims {
+            bones.push(*id);
+            if let Some(anim) = anim {
+                sets.extend(anim.clone().sets());
+            }
+        }
+        Motion { sets, bones }
+    }
+
+    ///Applies `f` to every curve of the motion
+    pub(crate) fn for_each_curve<F: FnMut(&mut Vec3)>(&mut self, mut f: F) {
+        for (_, anim) in self.anims.iter_mut() {
+            if let Some(anim) = anim {
+                for (_, v) in anim.curves_mut() {
+                    f(v);
+                }
+            }
+        }
+    }
+
     ///Order animations according to DIVA's ordering
     ///
     ///Ordering normally does work in DEBUG, but breaks expressions and fingers in PV
     ///In order to fix this, the anims must be sorted in DIVA's order
     pub fn sort(&mut self, motset_db: &MotionSetDatabase) {
         use crate::const_table::*;
-        self.anims.sort_by(|x, y| {
-            BONE_IDS
-                .get(&motset_db.bones[x.0][..])
-                .unwrap_or(&255)
-                .cmp(&BONE_IDS.get(&motset_db.bones[y.0][..]).unwrap_or(&255))
-        });
+        //`gblctr` isn't part of the skeleton but always comes first
+        let order = |id: usize| match &motset_db.bones[id][..] {
+            "gblctr" => None,
+            name => Some(*BONE_IDS.get(name).unwrap_or(&255)),
+        };
+        self.anims.sort_by_key(|x| order(x.0));
+    }
+}
+
+///`QualifiedMotion` with its bones referred to by name
+///
+///Bone ids depend on the motion set database, names are what should be used in hand edited dumps
+#[derive(Debug, PartialEq, PartialOrd, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NamedMotion {
+    pub anims: Vec<NamedAnim>,
+}
+
+#[derive(Debug, PartialEq, PartialOrd, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NamedAnim {
+    pub bone: String,
+    pub anim: Option<BoneAnim>,
+}
+
+impl QualifiedMotion {
+    pub fn to_named(&self, mot_db: &MotionSetDatabase) -> NamedMotion {
+        let anims = self
+            .anims
+            .iter()
+            .map(|(id, anim)| NamedAnim {
+                bone: mot_db.bones[*id].to_string(),
+                anim: anim.clone(),
+            })
+            .collect();
+        NamedMotion { anims }
+    }
+}
+
+impl NamedMotion {
+    ///Resolves the bone names back to ids, fails with the first name missing from `mot_db`
+    pub fn qualify(self, mot_db: &MotionSetDatabase) -> Result<QualifiedMotion, String> {
+        let mut anims = vec![];
+        for NamedAnim { bone, anim } in self.anims {
+            match mot_db.bones.iter().position(|x| x[..] == bone[..]) {
+                Some(id) => anims.push((id, anim)),
+                None => return Err(bone),
+            }
+        }
+        Ok(QualifiedMotion { anims })
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn named_json_roundtrip() {
+        let mot_db = mot_db();
+        let rot = Vec3 {
+            x: FrameData::Smooth(vec![InterpKeyframe {
+                keyframe: Keyframe {
+                    frame: 3,
+                    value: 0.5,
+                },
+                interpolation: 0.25,
+            }]),
+            ..Vec3::ZERO
+        };
+        let mot = QualifiedMotion {
+            anims: vec![(1, Some(BoneAnim::Rotation(rot))), (0, None)],
+        };
+        let json = serde_json::to_string(&mot.to_named(&mot_db)).unwrap();
+        assert!(json.contains(r#""bone":"kl_kubi""#));
+        assert!(json.contains(r#"{"frame":3,"value":0.5,"interpolation":0.25}"#));
+        let named: NamedMotion = serde_json::from_str(&json).unwrap();
+        assert_eq!(named.qualify(&mot_db), Ok(mot));
+    }
+
+    #[test]
+    fn skeleton_hierarchy() {
+        let bone_db = bone_db();
+        let nodes = skeleton_nodes(&bone_db.skeletons[0]);
+        let parent = |name: &str| {
+            let node = nodes.iter().find(|x| x.name == name).unwrap();
+            &nodes[node.parent.unwrap()].name[..]
+        };
+        assert_eq!(parent("j_ude_l_wj"), "j_kata_l_wj_cu");
+        assert_eq!(parent("kl_te_l_wj"), "j_ude_l_wj");
+        assert_eq!(parent("kl_asi_r_wj_co"), "j_sune_r_wj");
+        assert_eq!(parent("cl_momo_l"), "kl_kosi_etc_wj");
+        assert_eq!(parent("n_hara_b_wj_ex"), "n_hara_cd_ex");
+        let sune = nodes.iter().find(|x| x.name == "j_sune_l_wj").unwrap();
+        assert_eq!(sune.offset, (0.39, 0., 0.));
     }
 }
@@ -0,0 +1,188 @@
+use super::*;
+
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CsvOptions {
+    ///Use `'\t'` for TSV
+    pub delimiter: char,
+    ///Dump the keyframes of every set instead of sampling each frame
+    pub keyframes: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            keyframes: false,
+        }
+    }
+}
+
+fn short_name(curve: &str) -> &str {
+    match curve {
+        "position" => "pos",
+        "rotation" => "rot",
+        c => c,
+    }
+}
+
+impl QualifiedMotion {
+    ///Every set of the motion as `(column name, set)`, columns are named like `kl_kubi.rot.x`
+    fn csv_columns<'a>(&'a self, mot_db: &MotionSetDatabase) -> Vec<(String, &'a FrameData)> {
+        let mut columns = vec![];
+        for (id, anim) in &self.anims {
+            let anim = match anim {
+                Some(a) => a,
+                None => continue,
+            };
+            for (curve, v) in anim.curves() {
+                let name = format!("{}.{}", mot_db.bones[*id], short_name(curve));
+                columns.push((format!("{}.x", name), &v.x));
+                columns.push((format!("{}.y", name), &v.y));
+                columns.push((format!("{}.z", name), &v.z));
+            }
+        }
+        columns
+    }
+
+    ///Writes the motion as CSV
+    ///
+    ///By default there's a `frame` column followed by one column per set, with one row per frame
+    ///holding the evaluated curves. With `keyframes` set, every keyframe gets its own
+    ///`channel, type, frame, value, interpolation` row instead, poses have no frame
+    pub fn write_csv<W: io::Write>(
+        &self,
+        mot_db: &MotionSetDatabase,
+        options: CsvOptions,
+        mut writer: W,
+    ) -> io::Result<usize> {
+        let d = options.delimiter;
+        let columns = self.csv_columns(mot_db);
+        let mut rows = 0;
+        if options.keyframes {
+            writeln!(writer, "channel{0}type{0}frame{0}value{0}interpolation", d)?;
+            for (name, set) in columns {
+                match set {
+                    FrameData::None => (),
+                    FrameData::Pose(p) => {
+                        writeln!(writer, "{1}{0}pose{0}{0}{2}{0}", d, name, p)?;
+                        rows += 1;
+                    }
+                    FrameData::Linear(l) => {
+                        for k in l {
+                            writeln!(
+                                writer,
+                                "{1}{0}linear{0}{2}{0}{3}{0}",
+                                d, name, k.frame, k.value
+                            )?;
+                        }
+                        rows += l.len();
+                    }
+                    FrameData::Smooth(l) => {
+                        for k in l {
+                            let Keyframe { frame, value } = k.keyframe;
+                            let i = k.interpolation;
+                            writeln!(
+                                writer,
+                                "{1}{0}smooth{0}{2}{0}{3}{0}{4}",
+                                d, name, frame, value, i
+                            )?;
+                        }
+                        rows += l.len();
+                    }
+                }
+            }
+            return Ok(rows);
+        }
+
+        write!(writer, "frame")?;
+        for (name, _) in &columns {
+            write!(writer, "{}{}", d, name)?;
+        }
+        writeln!(writer)?;
+        for frame in 0..self.get_max_keyframe() {
+            write!(writer, "{}", frame)?;
+            for (_, set) in &columns {
+                write!(writer, "{}{}", d, set.interpolate(frame as f32))?;
+            }
+            writeln!(writer)?;
+            rows += 1;
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    fn sample() -> (MotionSetDatabase, QualifiedMotion) {
+        let rot = Vec3 {
+            x: FrameData::Linear(vec![
+                Keyframe {
+                    frame: 0,
+                    value: 0.,
+                },
+                Keyframe {
+                    frame: 2,
+                    value: 1.,
+                },
+            ]),
+            ..Vec3::ZERO
+        };
+        let mot = QualifiedMotion {
+            anims: vec![(0, None), (1, Some(BoneAnim::Rotation(rot)))],
+        };
+        (mot_db(), mot)
+    }
+
+    #[test]
+    fn csv_sampled() {
+        let (mot_db, mot) = sample();
+        let mut out = vec![];
+        let rows = mot
+            .write_csv(&mot_db, Default::default(), &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(rows, 3);
+        assert_eq!(
+            out,
+            "frame,kl_kubi.rot.x,kl_kubi.rot.y,kl_kubi.rot.z\n0,0,0,0\n1,0.5,0,0\n2,1,0,0\n"
+        );
+    }
+
+    #[test]
+    fn csv_rows_cover_every_component() {
+        let rot = Vec3 {
+            z: FrameData::Linear(vec![Keyframe {
+                frame: 4,
+                value: 1.,
+            }]),
+            ..Vec3::ZERO
+        };
+        let mot = QualifiedMotion {
+            anims: vec![(1, Some(BoneAnim::Rotation(rot)))],
+        };
+        let rows = mot
+            .write_csv(&mot_db(), Default::default(), vec![])
+            .unwrap();
+        assert_eq!(rows, 5);
+    }
+
+    #[test]
+    fn tsv_keyframes() {
+        let (mot_db, mot) = sample();
+        let options = CsvOptions {
+            delimiter: '\t',
+            keyframes: true,
+        };
+        let mut out = vec![];
+        mot.write_csv(&mot_db, options, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[1], "kl_kubi.rot.x\tlinear\t0\t0\t");
+        assert_eq!(lines[3], "kl_kubi.rot.y\tpose\t\t0\t");
+    }
+}
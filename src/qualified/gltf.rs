@@ -0,0 +1,735 @@
+use super::*;
+use crate::rotation::{euler_to_quat, quat_to_euler, unwrap_angle};
+
+use cgmath::{InnerSpace, Quaternion, Vector3};
+use serde_json::{json, Value};
+
+use std::io;
+
+//Step used to turn euler tangents into quaternion tangents
+const TANGENT_STEP: f32 = 1e-2;
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const GLB_JSON: &[u8] = b"JSON";
+const GLB_BIN: &[u8] = b"BIN\0";
+
+const FLOAT: u32 = 5126;
+
+impl Vec3 {
+    fn is_smooth(&self) -> bool {
+        [&self.x, &self.y, &self.z]
+            .iter()
+            .any(|x| matches!(x, FrameData::Smooth(_)))
+    }
+}
+
+#[derive(Default)]
+struct GltfBuilder {
+    buffer: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+    samplers: Vec<Value>,
+    channels: Vec<Value>,
+}
+
+impl GltfBuilder {
+    fn accessor(&mut self, data: &[f32], ty: &str, width: usize) -> usize {
+        let offset = self.buffer.len();
+        for v in data {
+            self.buffer.extend_from_slice(&v.to_le_bytes());
+        }
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": data.len() * 4,
+        }));
+        let mut accessor = json!({
+            "bufferView": self.views.len() - 1,
+            "componentType": FLOAT,
+            "count": data.len() / width,
+            "type": ty,
+        });
+        //Animation inputs are required to have bounds
+        if width == 1 {
+            let min = data.iter().cloned().fold(f32::INFINITY, f32::min);
+            let max = data.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            accessor["min"] = json!([min]);
+            accessor["max"] = json!([max]);
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn channel(&mut self, node: usize, path: &str, times: &[f32], values: &[f32], cubic: bool) {
+        let width = if path == "rotation" { 4 } else { 3 };
+        let ty = if width == 4 { "VEC4" } else { "VEC3" };
+        let input = self.accessor(times, "SCALAR", 1);
+        let output = self.accessor(values, ty, width);
+        let interpolation = if cubic { "CUBICSPLINE" } else { "LINEAR" };
+        self.samplers.push(json!({
+            "input": input,
+            "output": output,
+            "interpolation": interpolation,
+        }));
+        self.channels.push(json!({
+            "sampler": self.samplers.len() - 1,
+            "target": { "node": node, "path": path },
+        }));
+    }
+
+    fn translation(&mut self, node: usize, pos: &Vec3) {
+        let frames = keyframes_or_zero(pos);
+        let times: Vec<f32> = frames.iter().map(|&f| f as f32 / FPS).collect();
+        let cubic = pos.is_smooth();
+        let mut values = vec![];
+        for &frame in &frames {
+            let frame = frame as f32;
+            let value = pos.interpolate(frame);
+            if cubic {
+                //glTF tangents are per second
+                let (tin, tout) = pos.tangents(frame);
+                values.extend_from_slice(&vec_array(tin * FPS));
+                values.extend_from_slice(&vec_array(value));
+                values.extend_from_slice(&vec_array(tout * FPS));
+            } else {
+                values.extend_from_slice(&vec_array(value));
+            }
+        }
+        self.channel(node, "translation", &times, &values, cubic);
+    }
+
+    fn rotation(&mut self, node: usize, rot: &Vec3) {
+        let frames = keyframes_or_zero(rot);
+        let times: Vec<f32> = frames.iter().map(|&f| f as f32 / FPS).collect();
+        let cubic = rot.is_smooth();
+        let quat = |e: Vector3<f32>| euler_to_quat(e.x, e.y, e.z);
+        let mut values = vec![];
+        let mut prev: Option<Quaternion<f32>> = None;
+        for &frame in &frames {
+            let frame = frame as f32;
+            let euler = rot.interpolate(frame);
+            let mut q = quat(euler);
+            //Keep consecutive keys on the same hemisphere so they interpolate the short way
+            let sign = match prev {
+                Some(p) if p.dot(q) < 0. => -1.,
+                _ => 1.,
+            };
+            q *= sign;
+            prev = Some(q);
+            if cubic {
+                let (tin, tout) = rot.tangents(frame);
+                let qin = (q - quat(euler - tin * TANGENT_STEP) * sign) / TANGENT_STEP;
+                let qout = (quat(euler + tout * TANGENT_STEP) * sign - q) / TANGENT_STEP;
+                values.extend_from_slice(&quat_array(qin * FPS));
+                values.extend_from_slice(&quat_array(q));
+                values.extend_from_slice(&quat_array(qout * FPS));
+            } else {
+                values.extend_from_slice(&quat_array(q));
+            }
+        }
+        self.channel(node, "rotation", &times, &values, cubic);
+    }
+}
+
+//Nodes of the document, in index order
+#[derive(Default)]
+struct GltfNodes {
+    names: Vec<String>,
+    offsets: Vec<(f32, f32, f32)>,
+    children: Vec<Vec<usize>>,
+}
+
+impl GltfNodes {
+    fn push(&mut self, name: String, offset: (f32, f32, f32)) -> usize {
+        self.names.push(name);
+        self.offsets.push(offset);
+        self.children.push(vec![]);
+        self.names.len() - 1
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|x| x == name)
+    }
+}
+
+fn keyframes_or_zero(v: &Vec3) -> Vec<u16> {
+    let mut frames = v.keyframes();
+    if frames.is_empty() {
+        frames.push(0);
+    }
+    frames
+}
+
+fn vec_array(v: Vector3<f32>) -> [f32; 3] {
+    [v.x, v.y, v.z]
+}
+
+//glTF stores quaternions as XYZW
+fn quat_array(q: Quaternion<f32>) -> [f32; 4] {
+    [q.v.x, q.v.y, q.v.z, q.s]
+}
+
+impl QualifiedMotion {
+    ///Builds the glTF document and its binary buffer
+    ///
+    ///Nodes follow the first skeleton of `bone_db` and rest at the bones' offsets. They hang from
+    ///a `gblctr` node, which also holds the motion bones missing from the skeleton, and are the
+    ///joints of the document's skin. IK targets are placed on root nodes named `<bone>_target`.
+    ///`Smooth` curves become `CUBICSPLINE` samplers, everything else is `LINEAR`.
+    ///Fails with `InvalidInput` when `bone_db` has no skeleton
+    fn to_gltf<'a>(
+        &self,
+        mot_db: &MotionSetDatabase,
+        bone_db: &BoneDatabase<'a>,
+    ) -> io::Result<(Value, Vec<u8>)> {
+        let skel = bone_db.skeletons.first().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "bone_db has no skeleton")
+        })?;
+        let skeleton = skeleton_nodes(skel);
+        let mut nodes = GltfNodes::default();
+        let mut skeleton_roots = vec![];
+        for (i, node) in skeleton.iter().enumerate() {
+            nodes.push(node.name.clone(), node.offset);
+            match node.parent {
+                Some(p) => nodes.children[p].push(i),
+                None => skeleton_roots.push(i),
+            }
+        }
+        let root = match nodes.find("gblctr") {
+            Some(root) => root,
+            None => nodes.push("gblctr".into(), Default::default()),
+        };
+        nodes.children[root].append(&mut skeleton_roots);
+        let mut roots = vec![root];
+
+        let mut builder = GltfBuilder::default();
+        for (id, anim) in &self.anims {
+            let anim = match anim {
+                Some(a) => a,
+                None => continue,
+            };
+            let name = &mot_db.bones[*id][..];
+            let node = match nodes.find(name) {
+                Some(n) => n,
+                None => {
+                    let n = nodes.push(name.into(), Default::default());
+                    nodes.children[root].push(n);
+                    n
+                }
+            };
+            let (pos, rot) = anim.transform();
+            if let Some(pos) = pos {
+                builder.translation(node, pos);
+            }
+            if let Some(rot) = rot {
+                builder.rotation(node, rot);
+            }
+            let target = match anim {
+                BoneAnim::RotationIK { target, .. }
+                | BoneAnim::ArmIK { target, .. }
+                | BoneAnim::PositionIKRotation { target, .. } => target,
+                _ => continue,
+            };
+            let target_node = nodes.push(format!("{}_target", name), Default::default());
+            roots.push(target_node);
+            builder.translation(target_node, target);
+        }
+
+        //Everything but the targets
+        let joints: Vec<usize> = (0..nodes.names.len())
+            .filter(|x| !roots[1..].contains(x))
+            .collect();
+        let GltfNodes {
+            names,
+            offsets,
+            children,
+        } = nodes;
+        let nodes: Vec<Value> = names
+            .iter()
+            .zip(children.iter().zip(&offsets))
+            .map(|(name, (children, &(x, y, z)))| {
+                let mut node = json!({ "name": name });
+                if !children.is_empty() {
+                    node["children"] = json!(children);
+                }
+                if (x, y, z) != (0., 0., 0.) {
+                    node["translation"] = json!([x, y, z]);
+                }
+                node
+            })
+            .collect();
+        let gltf = json!({
+            "asset": { "version": "2.0", "generator": "mot" },
+            "scene": 0,
+            "scenes": [{ "nodes": roots }],
+            "nodes": nodes,
+            "skins": [{ "name": "skeleton", "skeleton": root, "joints": joints }],
+            "animations": [{
+                "name": "motion",
+                "samplers": builder.samplers,
+                "channels": builder.channels,
+            }],
+            "buffers": [{ "byteLength": builder.buffer.len() }],
+            "bufferViews": builder.views,
+            "accessors": builder.accessors,
+        });
+        Ok((gltf, builder.buffer))
+    }
+
+    ///Writes a binary glTF (`.glb`) with the skeleton and the motion as its single animation
+    pub fn write_glb<'a, W: io::Write>(
+        &self,
+        mot_db: &MotionSetDatabase,
+        bone_db: &BoneDatabase<'a>,
+        mut writer: W,
+    ) -> io::Result<usize> {
+        let (gltf, mut bin) = self.to_gltf(mot_db, bone_db)?;
+        let mut json = serde_json::to_vec(&gltf)?;
+        //Chunks are 4 byte aligned, JSON is padded with spaces
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+        let len = 12 + 8 + json.len() + 8 + bin.len();
+        writer.write_all(GLB_MAGIC)?;
+        writer.write_all(&2u32.to_le_bytes())?;
+        writer.write_all(&(len as u32).to_le_bytes())?;
+        writer.write_all(&(json.len() as u32).to_le_bytes())?;
+        writer.write_all(GLB_JSON)?;
+        writer.write_all(&json)?;
+        writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+        writer.write_all(GLB_BIN)?;
+        writer.write_all(&bin)?;
+        Ok(len)
+    }
+
+    ///Writes a `.gltf` document to `gltf` and its buffer to `bin`
+    ///
+    ///`bin_uri` is the path of the buffer relative to the document
+    pub fn write_gltf<'a, W: io::Write, B: io::Write>(
+        &self,
+        mot_db: &MotionSetDatabase,
+        bone_db: &BoneDatabase<'a>,
+        bin_uri: &str,
+        gltf: W,
+        mut bin: B,
+    ) -> io::Result<usize> {
+        let (mut doc, buffer) = self.to_gltf(mot_db, bone_db)?;
+        doc["buffers"][0]["uri"] = json!(bin_uri);
+        serde_json::to_writer_pretty(gltf, &doc)?;
+        bin.write_all(&buffer)?;
+        Ok(buffer.len())
+    }
+}
+
+///A translation or rotation curve read from a glTF sampler
+struct Track {
+    frames: Vec<u16>,
+    values: Vec<Vector3<f32>>,
+    //Per frame, only present for `CUBICSPLINE` samplers
+    tangents: Option<Vec<Vector3<f32>>>,
+}
+
+fn to_frame(time: f32) -> u16 {
+    (time * FPS).round().clamp(0., u16::MAX as f32) as u16
+}
+
+//DIVA keyframes have a single tangent, so glTF's in and out tangents are averaged
+fn split_cubic<T: Copy>(outputs: Vec<T>, cubic: bool) -> (Vec<T>, Option<Vec<(T, T)>>) {
+    if !cubic {
+        return (outputs, None);
+    }
+    let values = outputs.chunks(3).map(|x| x[1]).collect();
+    let tangents = outputs.chunks(3).map(|x| (x[0], x[2])).collect();
+    (values, Some(tangents))
+}
+
+impl Track {
+    fn translation(frames: Vec<u16>, outputs: Vec<[f32; 3]>, cubic: bool) -> Self {
+        let (values, tangents) = split_cubic(outputs, cubic);
+        let values = values.into_iter().map(Vector3::from).collect();
+        let tangents = tangents.map(|t| {
+            t.into_iter()
+                .map(|(i, o)| (Vector3::from(i) + Vector3::from(o)) / (2. * FPS))
+                .collect()
+        });
+        Self {
+            frames,
+            values,
+            tangents,
+        }
+    }
+
+    fn rotation(frames: Vec<u16>, outputs: Vec<[f32; 4]>, cubic: bool) -> Self {
+        let quat = |[x, y, z, w]: [f32; 4]| Quaternion::new(w, x, y, z);
+        let (values, tangents) = split_cubic(outputs, cubic);
+        let mut prev = Vector3::new(0., 0., 0.);
+        let euler = |q: Quaternion<f32>, prev: Vector3<f32>| {
+            let (x, y, z) = quat_to_euler(q.normalize());
+            Vector3::new(
+                unwrap_angle(prev.x, x),
+                unwrap_angle(prev.y, y),
+                unwrap_angle(prev.z, z),
+            )
+        };
+        let mut eulers = vec![];
+        let mut slopes = vec![];
+        for (i, value) in values.into_iter().enumerate() {
+            let q = quat(value);
+            let e = euler(q, prev);
+            if let Some(tangents) = &tangents {
+                let (tin, tout) = tangents[i];
+                let slope = (quat(tin) + quat(tout)) / (2. * FPS);
+                let next = euler(q + slope * TANGENT_STEP, e);
+                slopes.push((next - e) / TANGENT_STEP);
+            }
+            eulers.push(e);
+            prev = e;
+        }
+        Self {
+            frames,
+            values: eulers,
+            tangents: tangents.map(|_| slopes),
+        }
+    }
+
+    fn into_vec3(self) -> Vec3 {
+        let component = |c: usize| match &self.tangents {
+            _ if self.frames.len() == 1 => FrameData::Pose(self.values[0][c]),
+            Some(tangents) => FrameData::Smooth(
+                self.frames
+                    .iter()
+                    .zip(self.values.iter().zip(tangents.iter()))
+                    .map(|(&frame, (value, tangent))| InterpKeyframe {
+                        keyframe: Keyframe {
+                            frame,
+                            value: value[c],
+                        },
+                        interpolation: tangent[c],
+                    })
+                    .collect(),
+            ),
+            None => FrameData::Linear(
+                self.frames
+                    .iter()
+                    .zip(self.values.iter())
+                    .map(|(&frame, value)| Keyframe {
+                        frame,
+                        value: value[c],
+                    })
+                    .collect(),
+            ),
+        };
+        Vec3 {
+            x: component(0),
+            y: component(1),
+            z: component(2),
+        }
+    }
+}
+
+fn pose(v: Vector3<f32>) -> Vec3 {
+    Vec3 {
+        x: FrameData::Pose(v.x),
+        y: FrameData::Pose(v.y),
+        z: FrameData::Pose(v.z),
+    }
+}
+
+impl QualifiedMotion {
+    ///Reads the first animation of a binary glTF (`.glb`)
+    ///
+    ///Joints of the first skin (or every node when there's no skin) are matched by name against
+    ///`mot_db`'s bones, IK targets are read from nodes named `<bone>_target`. Only bones with a
+    ///channel on their node or their target are read, and animated IK bones without a target
+    ///node fail with an `InvalidData` error.
+    ///`CUBICSPLINE` samplers become `Smooth` sets, other samplers become `Linear` ones, and
+    ///curves without a channel keep the node's rest pose
+    pub fn read_glb<'a>(
+        data: &[u8],
+        mot_db: &MotionSetDatabase,
+        bone_db: &BoneDatabase<'a>,
+    ) -> Result<Self, ::gltf::Error> {
+        use ::gltf::animation::util::ReadOutputs;
+        use ::gltf::animation::Interpolation;
+        use ::gltf::buffer::Source;
+
+        let ::gltf::Gltf { document, blob } = ::gltf::Gltf::from_slice(data)?;
+        let buffer = |b: ::gltf::Buffer| match b.source() {
+            Source::Bin => blob.as_deref(),
+            _ => None,
+        };
+
+        let nodes: Vec<_> = document.nodes().collect();
+        let mut translations: Vec<Option<Track>> = nodes.iter().map(|_| None).collect();
+        let mut rotations: Vec<Option<Track>> = nodes.iter().map(|_| None).collect();
+        if let Some(animation) = document.animations().next() {
+            for channel in animation.channels() {
+                let reader = channel.reader(buffer);
+                let cubic = channel.sampler().interpolation() == Interpolation::CubicSpline;
+                let node = channel.target().node().index();
+                let frames = match reader.read_inputs() {
+                    Some(i) => i.map(to_frame).collect(),
+                    None => continue,
+                };
+                match reader.read_outputs() {
+                    Some(ReadOutputs::Translations(t)) => {
+                        translations[node] = Some(Track::translation(frames, t.collect(), cubic))
+                    }
+                    Some(ReadOutputs::Rotations(r)) => {
+                        rotations[node] =
+                            Some(Track::rotation(frames, r.into_f32().collect(), cubic))
+                    }
+                    _ => continue,
+                }
+            }
+        }
+
+        let joints: Vec<usize> = match document.skins().next() {
+            Some(skin) => skin.joints().map(|x| x.index()).collect(),
+            None => (0..nodes.len()).collect(),
+        };
+        let animated: Vec<bool> = (0..nodes.len())
+            .map(|x| translations[x].is_some() || rotations[x].is_some())
+            .collect();
+        let rest = |node: usize| {
+            let (t, r, _) = nodes[node].transform().decomposed();
+            let (x, y, z) = quat_to_euler(Quaternion::new(r[3], r[0], r[1], r[2]));
+            (Vector3::from(t), Vector3::new(x, y, z))
+        };
+        let mut position = |node: usize| match translations[node].take() {
+            Some(t) => t.into_vec3(),
+            None => pose(rest(node).0),
+        };
+        let mut rotation = |node: usize| match rotations[node].take() {
+            Some(t) => t.into_vec3(),
+            None => pose(rest(node).1),
+        };
+        let by_name = |name: &str| nodes.iter().position(|x| x.name() == Some(name));
+
+        let mut anims = vec![];
+        for node in joints {
+            let name = match nodes[node].name() {
+                Some(n) => n,
+                None => continue,
+            };
+            let id = match mot_db.bones.iter().position(|x| &x[..] == name) {
+                Some(id) => id,
+                None => continue,
+            };
+            let bone = match find_bone(name, bone_db) {
+                Some(b) => b,
+                None => continue,
+            };
+            let target = by_name(&format!("{}_target", name));
+            if !animated[node] && !target.map(|x| animated[x]).unwrap_or(false) {
+                continue;
+            }
+            let target = target.ok_or_else(|| {
+                ::gltf::Error::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("IK bone `{}` has no `{}_target` node", name, name),
+                ))
+            });
+            let anim = match bone.mode {
+                BoneType::Rotation => BoneAnim::Rotation(rotation(node)),
+                BoneType::Type1 => continue,
+                BoneType::Position => BoneAnim::Position(position(node)),
+                BoneType::Type3 => BoneAnim::PositionRotation {
+                    position: position(node),
+                    rotation: rotation(node),
+                },
+                BoneType::Type4 => BoneAnim::RotationIK {
+                    target: position(target?),
+                    rotation: rotation(node),
+                },
+                BoneType::Type5 => BoneAnim::ArmIK {
+                    target: position(target?),
+                    rotation: rotation(node),
+                },
+                BoneType::Type6 => BoneAnim::PositionIKRotation {
+                    position: position(node),
+                    target: position(target?),
+                },
+            };
+            anims.push((id, Some(anim)));
+        }
+        let mut mot = QualifiedMotion { anims };
+        mot.sort(mot_db);
+        Ok(mot)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    fn sample() -> (MotionSetDatabase, BoneDatabase<'static>, QualifiedMotion) {
+        let smooth = FrameData::Smooth(vec![
+            InterpKeyframe {
+                keyframe: Keyframe {
+                    frame: 0,
+                    value: 0.,
+                },
+                interpolation: 0.1,
+            },
+            InterpKeyframe {
+                keyframe: Keyframe {
+                    frame: 10,
+                    value: 1.,
+                },
+                interpolation: 0.,
+            },
+        ]);
+        let rot = Vec3 {
+            y: smooth,
+            ..Vec3::ZERO
+        };
+        let mot = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(Vec3::ZERO))),
+                (1, Some(BoneAnim::Rotation(rot))),
+            ],
+        };
+        (mot_db(), bone_db(), mot)
+    }
+
+    #[test]
+    fn gltf_samplers() {
+        let (mot_db, bone_db, mot) = sample();
+        let (gltf, bin) = mot.to_gltf(&mot_db, &bone_db).unwrap();
+        let samplers = gltf["animations"][0]["samplers"].as_array().unwrap();
+        assert_eq!(samplers[0]["interpolation"], "LINEAR");
+        assert_eq!(samplers[1]["interpolation"], "CUBICSPLINE");
+        //1 key of translation, 2 cubic keys of rotation with their tangents
+        assert_eq!(bin.len(), (1 + 3 + 2 + 2 * 3 * 4) * 4);
+        //gblctr isn't part of the skeleton, it's added as the root of the skin's joints
+        let nodes = skeleton_nodes(&bone_db.skeletons[0]).len() + 1;
+        assert_eq!(gltf["nodes"].as_array().unwrap().len(), nodes);
+        assert_eq!(gltf["nodes"][nodes - 1]["name"], "gblctr");
+        assert_eq!(gltf["skins"][0]["joints"].as_array().unwrap().len(), nodes);
+        assert_eq!(gltf["skins"][0]["skeleton"], nodes - 1);
+        //The skeleton rests at its bones' offsets instead of collapsing at the origin
+        let kubi = gltf["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|x| x["name"] == "kl_kubi")
+            .unwrap();
+        let y = kubi["translation"][1].as_f64().unwrap();
+        assert!((y - 0.145).abs() < 1e-5);
+    }
+
+    #[test]
+    fn gltf_ik_targets() {
+        let (mot_db, bone_db, _) = sample();
+        let target = Vec3 {
+            x: FrameData::Pose(0.3),
+            ..Vec3::ZERO
+        };
+        let mot = QualifiedMotion {
+            anims: vec![(
+                bone_id("c_kata_l"),
+                Some(BoneAnim::ArmIK {
+                    target,
+                    rotation: Vec3::ZERO,
+                }),
+            )],
+        };
+        let (gltf, _) = mot.to_gltf(&mot_db, &bone_db).unwrap();
+        let nodes = gltf["nodes"].as_array().unwrap();
+        let target = nodes.len() - 1;
+        assert_eq!(nodes[target]["name"], "c_kata_l_target");
+        //Targets are scene roots but not joints
+        let is_listed = |v: &Value| v.as_array().unwrap().contains(&json!(target));
+        assert!(is_listed(&gltf["scenes"][0]["nodes"]));
+        assert!(!is_listed(&gltf["skins"][0]["joints"]));
+        let channels = gltf["animations"][0]["channels"].as_array().unwrap();
+        assert!(channels.iter().any(|x| x["target"]["node"] == target));
+
+        let mut empty = bone_db;
+        empty.skeletons.clear();
+        let err = mot.to_gltf(&mot_db, &empty).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn glb_roundtrip() {
+        let (mot_db, bone_db, mot) = sample();
+        let mut glb = vec![];
+        mot.write_glb(&mot_db, &bone_db, &mut glb).unwrap();
+        let read = QualifiedMotion::read_glb(&glb, &mot_db, &bone_db).unwrap();
+        //Skeleton bones without channels are left out
+        let ids: Vec<usize> = read.anims.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [0, 1]);
+        let (_, rot) = read.anims.iter().find(|(id, _)| *id == 1).unwrap();
+        let rot = match rot {
+            Some(BoneAnim::Rotation(rot)) => rot,
+            e => panic!("unexpected anim {:?}", e),
+        };
+        match &rot.y {
+            FrameData::Smooth(keys) => {
+                assert_eq!(keys.len(), 2);
+                assert!((keys[0].interpolation - 0.1).abs() < 1e-3);
+                assert!((keys[1].keyframe.value - 1.).abs() < 1e-5);
+            }
+            e => panic!("expected a smooth set, got {:?}", e),
+        }
+        for frame in 0..10 {
+            let frame = frame as f32;
+            assert!((rot.y.interpolate(frame) - mot_y(&mot).interpolate(frame)).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn glb_ik_roundtrip() {
+        let (mot_db, bone_db, _) = sample();
+        let target = Vec3 {
+            x: FrameData::Linear(vec![
+                Keyframe {
+                    frame: 0,
+                    value: 0.3,
+                },
+                Keyframe {
+                    frame: 5,
+                    value: 0.1,
+                },
+            ]),
+            ..Vec3::ZERO
+        };
+        let mot = QualifiedMotion {
+            anims: vec![(
+                bone_id("c_kata_l"),
+                Some(BoneAnim::ArmIK {
+                    target: target.clone(),
+                    rotation: Vec3::ZERO,
+                }),
+            )],
+        };
+        let mut glb = vec![];
+        mot.write_glb(&mot_db, &bone_db, &mut glb).unwrap();
+        let read = QualifiedMotion::read_glb(&glb, &mot_db, &bone_db).unwrap();
+        match &read.anims[..] {
+            [(id, Some(BoneAnim::ArmIK { target: t, .. }))] if *id == bone_id("c_kata_l") => {
+                assert_eq!(t.x, target.x)
+            }
+            e => panic!("unexpected anims {:?}", e),
+        }
+
+        //The node's name is in the JSON chunk, renaming it keeps the file valid
+        let at = glb.windows(15).position(|x| x == b"c_kata_l_target");
+        glb[at.unwrap() + 9..][..6].copy_from_slice(b"tarjet");
+        let err = QualifiedMotion::read_glb(&glb, &mot_db, &bone_db).unwrap_err();
+        assert!(matches!(err, ::gltf::Error::Io(e) if e.kind() == io::ErrorKind::InvalidData));
+    }
+
+    fn mot_y(mot: &QualifiedMotion) -> &FrameData {
+        match &mot.anims[1].1 {
+            Some(BoneAnim::Rotation(rot)) => &rot.y,
+            _ => unreachable!(),
+        }
+    }
+}
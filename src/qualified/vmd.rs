@@ -0,0 +1,334 @@
+use super::*;
+use crate::retarget::chain_links;
+use crate::rotation::euler_to_quat;
+
+use cgmath::{Quaternion, Rad, Rotation, Rotation3, Vector3, Zero};
+use diva_db::bone::Skeleton;
+
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+use std::io;
+
+const VMD_MAGIC: &[u8] = b"Vocaloid Motion Data 0002";
+///DIVA works in meters while an MMD unit is roughly 8cm
+const VMD_SCALE: f32 = 12.5;
+///MMD plays motions at 30fps
+const VMD_FPS: f32 = 30.;
+///Bezier control points (x1, y1, x2, y2) of a linear curve
+const VMD_LINEAR: [u8; 4] = [20, 20, 107, 107];
+
+///Shift-JIS names of the standard MMD bones driven by the DIVA bones
+///
+///The names are the ones shared by most MMD models, each DIVA bone is matched with the MMD bone
+///moving the same part of the body. Arms, chest and face are driven by IK in DIVA while MMD
+///poses them directly, so they're matched through the FK bones `to_fk` writes. Legs use IK on
+///both sides, or their FK bones once `to_fk` has baked them
+const VMD_BONES: &[(&str, &[u8])] = &[
+    //全ての親
+    ("gblctr", b"\x91S\x82\xC4\x82\xCC\x90e"),
+    //センター
+    ("n_hara_cp", b"\x83Z\x83\x93\x83^\x81["),
+    //上半身
+    ("j_mune_wj", b"\x8F\xE3\x94\xBC\x90g"),
+    //上半身2
+    ("kl_mune_b_wj", b"\x8F\xE3\x94\xBC\x90g2"),
+    //下半身
+    ("kl_kosi_y", b"\x89\xBA\x94\xBC\x90g"),
+    //首
+    ("kl_kubi", b"\x8E\xF1"),
+    //頭
+    ("j_kao_wj", b"\x93\xAA"),
+    //左腕
+    ("j_kata_l_wj_cu", b"\x8D\xB6\x98r"),
+    //右腕
+    ("j_kata_r_wj_cu", b"\x89E\x98r"),
+    //左ひじ
+    ("j_ude_l_wj", b"\x8D\xB6\x82\xD0\x82\xB6"),
+    //右ひじ
+    ("j_ude_r_wj", b"\x89E\x82\xD0\x82\xB6"),
+    //左手首
+    ("kl_te_l_wj", b"\x8D\xB6\x8E\xE8\x8E\xF1"),
+    //右手首
+    ("kl_te_r_wj", b"\x89E\x8E\xE8\x8E\xF1"),
+    //左足ＩＫ
+    ("cl_momo_l", b"\x8D\xB6\x91\xAB\x82h\x82j"),
+    //右足ＩＫ
+    ("cl_momo_r", b"\x89E\x91\xAB\x82h\x82j"),
+    //左足
+    ("j_momo_l_wj", b"\x8D\xB6\x91\xAB"),
+    //右足
+    ("j_momo_r_wj", b"\x89E\x91\xAB"),
+    //左ひざ
+    ("j_sune_l_wj", b"\x8D\xB6\x82\xD0\x82\xB4"),
+    //右ひざ
+    ("j_sune_r_wj", b"\x89E\x82\xD0\x82\xB4"),
+    //左足首
+    ("kl_asi_l_wj_co", b"\x8D\xB6\x91\xAB\x8E\xF1"),
+    //右足首
+    ("kl_asi_r_wj_co", b"\x89E\x91\xAB\x8E\xF1"),
+    //左つま先
+    ("kl_toe_l_wj", b"\x8D\xB6\x82\xC2\x82\xDC\x90\xE6"),
+    //右つま先
+    ("kl_toe_r_wj", b"\x89E\x82\xC2\x82\xDC\x90\xE6"),
+    //左目
+    ("kl_eye_l", b"\x8D\xB6\x96\xDA"),
+    //右目
+    ("kl_eye_r", b"\x89E\x96\xDA"),
+];
+
+///Default DIVA to MMD bone name mapping, the names are Shift-JIS encoded as VMD expects
+pub fn vmd_bone_names() -> HashMap<String, Vec<u8>> {
+    VMD_BONES
+        .iter()
+        .map(|(diva, mmd)| (diva.to_string(), mmd.to_vec()))
+        .collect()
+}
+
+fn write_fixed<W: io::Write>(writer: &mut W, s: &[u8], len: usize) -> io::Result<()> {
+    let mut buf = vec![0u8; len];
+    let n = s.len().min(len);
+    buf[..n].copy_from_slice(&s[..n]);
+    writer.write_all(&buf)
+}
+
+fn vmd_interpolation() -> [u8; 64] {
+    //The first row holds each control point for X, Y, Z and rotation in turn,
+    //the remaining rows are the same table shifted by one byte each
+    let mut row = [0u8; 16];
+    for (i, point) in VMD_LINEAR.iter().enumerate() {
+        for c in 0..4 {
+            row[i * 4 + c] = *point;
+        }
+    }
+    let mut table = [0u8; 64];
+    for r in 0..4 {
+        table[r * 16..r * 16 + 16 - r].copy_from_slice(&row[r..]);
+    }
+    table
+}
+
+//Where `name` rests, MMD keys translations relative to the rest pose
+//
+//The skeleton only has the offsets of the bones from their parent, which is enough for most bones.
+//The hips and the leg IK targets are placed in the world though, so they rest standing on straight
+//legs with the heels `heel_height` above the ground. The hips are turned a quarter around Y like
+//the game's motions turn n_hara
+fn rest_position(skel: &Skeleton, name: &str) -> Vector3<f32> {
+    let positions = bone_positions(skel);
+    let offset = |name: &str| {
+        let index = skel.bones.iter().position(|x| x.name == name)?;
+        positions[index]
+            .first()
+            .map(|&(x, y, z)| Vector3::new(x, y, z))
+    };
+    let hip = |leg: &str| {
+        let (a, b) = chain_links(skel, leg)?;
+        let offset = Quaternion::from_angle_y(Rad(FRAC_PI_2)).rotate_vector(offset(leg)?);
+        Some((offset, a + b))
+    };
+    let rest = match name {
+        "n_hara_cp" => {
+            hip("cl_momo_l").map(|(o, len)| Vector3::new(0., skel.heel_height + len - o.y, 0.))
+        }
+        "cl_momo_l" | "cl_momo_r" => {
+            hip(name).map(|(o, _)| Vector3::new(o.x, skel.heel_height, o.z))
+        }
+        _ => offset(name),
+    };
+    rest.unwrap_or_else(Vector3::zero)
+}
+
+impl QualifiedMotion {
+    ///Writes the motion as a VMD bone motion
+    ///
+    ///Bones are named through `names` (see `vmd_bone_names`), falling back to their `mot_db`
+    ///names when they aren't mapped, keyframes are emitted on every frame any of the bone's
+    ///curves has a key, converted to MMD's 30fps, and the coordinates are converted to MMD's
+    ///left-handed system. Keys landing on the same MMD frame are merged
+    ///
+    ///Translations are written relative to the bones' rest position in `skel`. Leg IK bones
+    ///write their target, the other IK bones are left out: use `to_fk` first to get them as
+    ///rotations of their FK bones, which bakes the legs too. Fails with `InvalidInput` when
+    ///`model_name` isn't ASCII, since VMD expects Shift-JIS
+    pub fn write_vmd<W: io::Write>(
+        &self,
+        mot_db: &MotionSetDatabase,
+        skel: &Skeleton,
+        names: &HashMap<String, Vec<u8>>,
+        model_name: &str,
+        mut writer: W,
+    ) -> io::Result<usize> {
+        if !model_name.is_ascii() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "VMD model names have to be ASCII",
+            ));
+        }
+        let mut frames = vec![];
+        for (id, anim) in &self.anims {
+            let (pos, rot) = match anim {
+                //The first curve of the leg IK is where the ankle is in the world
+                Some(BoneAnim::PositionIKRotation { position, .. }) => (Some(position), None),
+                Some(BoneAnim::ArmIK { .. }) | Some(BoneAnim::RotationIK { .. }) => continue,
+                Some(a) => a.transform(),
+                None => continue,
+            };
+            if let (None, None) = (pos, rot) {
+                continue;
+            }
+            let mut keys: Vec<u16> = pos.map(Vec3::keyframes).unwrap_or_default();
+            keys.append(&mut rot.map(Vec3::keyframes).unwrap_or_default());
+            keys.push(0);
+            let mut keys: Vec<u32> = keys
+                .into_iter()
+                .map(|x| (x as f32 * VMD_FPS / FPS).round() as u32)
+                .collect();
+            keys.sort_unstable();
+            keys.dedup();
+            let name = &mot_db.bones[*id];
+            let rest = rest_position(skel, name);
+            let name = names.get(name).map(|x| &x[..]).unwrap_or(name.as_bytes());
+            for frame in keys {
+                let time = frame as f32 * FPS / VMD_FPS;
+                let p = pos
+                    .map(|p| (p.interpolate(time) - rest) * VMD_SCALE)
+                    .unwrap_or_else(Vector3::zero);
+                let q = rot
+                    .map(|r| {
+                        let r = r.interpolate(time);
+                        euler_to_quat(r.x, r.y, r.z)
+                    })
+                    .unwrap_or_else(|| Quaternion::new(1., 0., 0., 0.));
+                frames.push((name, frame, p, q));
+            }
+        }
+
+        let interpolation = vmd_interpolation();
+        write_fixed(&mut writer, VMD_MAGIC, 30)?;
+        write_fixed(&mut writer, model_name.as_bytes(), 20)?;
+        writer.write_all(&(frames.len() as u32).to_le_bytes())?;
+        for (name, frame, p, q) in &frames {
+            write_fixed(&mut writer, name, 15)?;
+            writer.write_all(&frame.to_le_bytes())?;
+            //Flipping Z mirrors the rotation axes' X and Y
+            for v in &[p.x, p.y, -p.z, -q.v.x, -q.v.y, q.v.z, q.s] {
+                writer.write_all(&v.to_le_bytes())?;
+            }
+            writer.write_all(&interpolation)?;
+        }
+        //Morph, camera, light and self shadow keyframe counts
+        writer.write_all(&[0; 16])?;
+        Ok(30 + 20 + 4 + frames.len() * 111 + 16)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    use cgmath::InnerSpace;
+
+    use std::convert::TryInto;
+
+    #[test]
+    fn vmd_write() {
+        let mot_db = mot_db();
+        let rot = Vec3 {
+            x: FrameData::Linear(vec![
+                Keyframe {
+                    frame: 0,
+                    value: 0.,
+                },
+                Keyframe {
+                    frame: 3,
+                    value: 0.5,
+                },
+                Keyframe {
+                    frame: 4,
+                    value: 1.,
+                },
+            ]),
+            ..Vec3::ZERO
+        };
+        let mot = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(Vec3::ZERO))),
+                (1, Some(BoneAnim::Rotation(rot))),
+            ],
+        };
+        let (bone_db, mut out) = (bone_db(), vec![]);
+        let skel = &bone_db.skeletons[0];
+        let mut names = vmd_bone_names();
+        names.insert("kl_kubi".into(), b"neck".to_vec());
+        let len = mot
+            .write_vmd(&mot_db, skel, &names, "miku", &mut out)
+            .unwrap();
+        assert_eq!(len, out.len());
+        assert_eq!(&out[..VMD_MAGIC.len()], VMD_MAGIC);
+        //gblctr has a single key at 0, kl_kubi's keys at 3 and 4 both land on MMD's frame 2
+        assert_eq!(&out[50..54], &3u32.to_le_bytes());
+        assert_eq!(&out[54..54 + 8], b"\x91S\x82\xC4\x82\xCC\x90e");
+        assert_eq!(&out[54 + 111..54 + 111 + 5], b"neck\0");
+        let last = 54 + 2 * 111 + 15;
+        assert_eq!(&out[last..last + 4], &2u32.to_le_bytes());
+
+        let err = mot.write_vmd(&mot_db, skel, &names, "ミク", vec![]);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn vmd_rest_pose() {
+        let (mot_db, bone_db) = (mot_db(), bone_db());
+        let skel = &bone_db.skeletons[0];
+        let pose = |v: Vector3<f32>| Vec3 {
+            x: FrameData::Pose(v.x),
+            y: FrameData::Pose(v.y),
+            z: FrameData::Pose(v.z),
+        };
+        let hips = rest_position(skel, "n_hara_cp");
+        let ankle = rest_position(skel, "cl_momo_l");
+        //Straight legs of the common skeleton, the game's motions keep the hips around 1.05
+        assert!((hips.y - 1.056).abs() < 1e-3);
+        assert!((ankle - Vector3::new(0.065, 0.103, -0.046)).magnitude() < 1e-3);
+        let mot = QualifiedMotion {
+            anims: vec![
+                (
+                    bone_id("n_hara_cp"),
+                    Some(BoneAnim::PositionRotation {
+                        position: pose(hips + Vector3::unit_y()),
+                        rotation: Vec3::ZERO,
+                    }),
+                ),
+                (
+                    bone_id("cl_momo_l"),
+                    Some(BoneAnim::PositionIKRotation {
+                        position: pose(ankle),
+                        target: Vec3::ZERO,
+                    }),
+                ),
+                (
+                    bone_id("c_kata_l"),
+                    Some(BoneAnim::ArmIK {
+                        target: Vec3::ZERO,
+                        rotation: Vec3::ZERO,
+                    }),
+                ),
+            ],
+        };
+        let mut out = vec![];
+        mot.write_vmd(&mot_db, skel, &vmd_bone_names(), "miku", &mut out)
+            .unwrap();
+        //The arm IK is left out
+        assert_eq!(&out[50..54], &2u32.to_le_bytes());
+        let position = |record: usize| {
+            let at = 54 + record * 111 + 19;
+            let f =
+                |i: usize| f32::from_le_bytes(out[at + i * 4..at + i * 4 + 4].try_into().unwrap());
+            Vector3::new(f(0), f(1), f(2))
+        };
+        assert!((position(0) - Vector3::unit_y() * VMD_SCALE).magnitude() < 1e-4);
+        assert_eq!(&out[54 + 111..54 + 111 + 8], b"\x8D\xB6\x91\xAB\x82h\x82j");
+        assert!(position(1).magnitude() < 1e-4);
+    }
+}
@@ -34,14 +34,14 @@ impl Vec3 {
     fn get_max_keyframe(&self) -> u16 {
         use std::cmp::max;
         let x = self.x.get_max_keyframe();
-        let y = self.x.get_max_keyframe();
-        let z = self.x.get_max_keyframe();
+        let y = self.y.get_max_keyframe();
+        let z = self.z.get_max_keyframe();
         max(max(x, y), z)
     }
     pub(crate) fn get_bits(&self) -> Vec<u8> {
         let x = self.x.get_bits();
-        let y = self.x.get_bits();
-        let z = self.x.get_bits();
+        let y = self.y.get_bits();
+        let z = self.z.get_bits();
         vec![x, y, z]
     }
 }
@@ -204,7 +204,7 @@ impl QualifiedMotion {
             })
             .collect()
     }
-    fn get_max_keyframe(&self) -> u16 {
+    pub(crate) fn get_max_keyframe(&self) -> u16 {
         use std::cmp::max;
         use BoneAnim::*;
         self.anims
@@ -254,3 +254,36 @@ impl QualifiedMotion {
         Ok((end - begin) as usize)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_vec3_components() {
+        let rot = Vec3 {
+            x: FrameData::Pose(1.),
+            y: FrameData::Linear(vec![Keyframe {
+                frame: 10,
+                value: 2.,
+            }]),
+            z: FrameData::Smooth(vec![InterpKeyframe {
+                keyframe: Keyframe {
+                    frame: 4,
+                    value: 3.,
+                },
+                interpolation: 0.,
+            }]),
+        };
+        assert_eq!(rot.get_bits(), vec![1, 2, 3]);
+        let mot = QualifiedMotion {
+            anims: vec![(0, Some(BoneAnim::Rotation(rot)))],
+        };
+        let mut out = io::Cursor::new(vec![]);
+        mot.write(&mut out).unwrap();
+        let out = out.into_inner();
+        //The frame count follows the last keyframe of y, then come the set types
+        assert_eq!(&out[34..36], &11u16.to_le_bytes());
+        assert_eq!(out[36], 1 | 2 << 2 | 3 << 4);
+    }
+}
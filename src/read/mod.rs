@@ -6,7 +6,7 @@ use super::*;
 
 // mod utilities;
 
-#[derive(Debug, PartialEq, PartialOrd)]
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
 pub enum SetType {
     None,
     Pose,
@@ -93,11 +93,12 @@ impl SetType {
 }
 
 //TODO: merge these 2 functions
-fn parse_linear(endian: Endianness) -> impl Fn(&[u8]) -> IResult<&[u8], Vec<Keyframe>> {
+fn parse_linear(_endian: Endianness) -> impl Fn(&[u8]) -> IResult<&[u8], Vec<Keyframe>> {
+    use nom::combinator::map;
     use nom::multi::*;
     use nom::number::complete::*;
     move |i0: &[u8]| {
-        let (i, c) = u32_usize(endian)(i0)?;
+        let (i, c) = map(le_u16, |n| n as usize)(i0)?;
         let (i, frames) = count(le_u16, c)(i)?;
         //Align at 4th byte
         let i = &i[i.len() % 4..];
@@ -138,11 +139,61 @@ fn parse_smooth(_endian: Endianness) -> impl Fn(&[u8]) -> IResult<&[u8], Vec<Int
 
 impl DeserializeEndian for Motion {
     fn parse(i: &[u8], endian: Endianness) -> IResult<&[u8], Self> {
+        Motion::parse_header(i, i, endian)
+    }
+}
+
+impl Motion {
+    ///Parses every motion of a file holding several, like the `mot_PVxxx.bin` files
+    ///
+    ///Files with a single motion are read as a set of one
+    pub fn parse_set(i: &[u8], endian: Endianness) -> IResult<&[u8], Vec<Self>> {
+        let mut motions = vec![];
+        let mut header = i;
+        loop {
+            let (_, offset) = u32_usize(endian)(header)?;
+            if offset == 0 {
+                break;
+            }
+            let (next, mot) = Motion::parse_header(i, header, endian)?;
+            motions.push(mot);
+            header = next;
+        }
+        Ok((header, motions))
+    }
+
+    ///Reads the frame count stored in the header of every motion of the file
+    ///
+    ///Keys at or past it aren't played, see `Motion::validate`
+    pub fn parse_frame_counts(i: &[u8], endian: Endianness) -> IResult<&[u8], Vec<u16>> {
+        use nom::bytes::complete::take;
+        use nom::number::complete::le_u16;
+        use nom::sequence::preceded;
+
+        let mut counts = vec![];
+        let mut header = i;
+        loop {
+            let (_, offset) = u32_usize(endian)(header)?;
+            if offset == 0 {
+                break;
+            }
+            let (_, count) = offset_then(i, preceded(le_u16, le_u16), endian)(header)?;
+            counts.push(count);
+            header = take(16usize)(header)?.0;
+        }
+        Ok((header, counts))
+    }
+
+    //Parses the motion whose offsets start at `header`, offsets are relative to `i`
+    fn parse_header<'a>(
+        i: &'a [u8],
+        header: &'a [u8],
+        endian: Endianness,
+    ) -> IResult<&'a [u8], Self> {
         use nom::combinator::map;
         use nom::number::complete::*;
 
-        let (i0, count) = offset_then(i, map(le_u16, |n| (n & 0x3FFF) as usize), endian)(i)?;
-        println!("Set count: {}", count);
+        let (i0, count) = offset_then(i, map(le_u16, |n| (n & 0x3FFF) as usize), endian)(header)?;
         let (i0, types) = offset_then(i, SetType::parse_multi(count), endian)(i0)?;
         let (i0, ks_offset) = u32_usize(endian)(i0)?;
         let (i0, bones) = offset_then(i, many_until_nth(le_u16, 0, 1), endian)(i0)?;
@@ -182,6 +233,7 @@ impl DeserializeEndian for Motion {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::validate::IssueKind;
 
     const DB: &[u8] = include_bytes!("../../assets/bone_data.bin");
     const INPUT: &[u8] = include_bytes!("../../assets/mot_PV001.bin");
@@ -212,6 +264,45 @@ mod test {
             [None, Pose, Linear, Smooth, Smooth, Pose, None, Smooth]
         )
     }
+    #[test]
+    fn motion_set_roundtrip() {
+        let mot = |pose: f32, bones: Vec<usize>| Motion {
+            sets: vec![FrameData::Pose(pose), FrameData::None, FrameData::Pose(-pose)],
+            bones,
+        };
+        let motions = vec![mot(1., vec![0]), mot(2., vec![0, 3, 4])];
+        let mut out = vec![];
+        let len = Motion::write_set(&motions, &mut out).unwrap();
+        assert_eq!(len, out.len());
+        let (_, read) = Motion::parse_set(&out, Endianness::Little).unwrap();
+        assert_eq!(read.len(), 2);
+        for (a, b) in read.iter().zip(&motions) {
+            assert_eq!(a.sets, b.sets);
+            assert_eq!(a.bones, b.bones);
+        }
+    }
+
+    #[test]
+    fn linear_roundtrip() {
+        //Linear keysets are written with a u16 key count, like smooth ones
+        let keys = (0..3)
+            .map(|x| Keyframe {
+                frame: x * 4,
+                value: x as f32,
+            })
+            .collect();
+        let mot = Motion {
+            sets: vec![FrameData::Linear(keys), FrameData::Pose(1.)],
+            bones: vec![0],
+        };
+        let mut out = vec![];
+        Motion::write_set(&[mot], &mut out).unwrap();
+        let (_, read) = Motion::parse_set(&out, Endianness::Little).unwrap();
+        assert_eq!(read[0].sets[0].keyframes(), [0, 4, 8]);
+        assert_eq!(read[0].sets[0].interpolate(6.), 1.5);
+        assert_eq!(read[0].sets[1], FrameData::Pose(1.));
+    }
+
     #[test]
     fn motion_test() {
         let (_, val) = Motion::parse(INPUT, Endianness::Little).unwrap();
@@ -224,4 +315,19 @@ mod test {
             val.bones.iter().position(|&r| r == 71).unwrap()
         );
     }
+
+    #[test]
+    fn frame_counts() {
+        let (_, counts) = Motion::parse_frame_counts(INPUT, Endianness::Little).unwrap();
+        let (_, motions) = Motion::parse_set(INPUT, Endianness::Little).unwrap();
+        assert_eq!(counts, [9301]);
+        let out_of_range = |count| {
+            let issues = motions[0].validate(count).into_iter();
+            issues
+                .filter(|x| matches!(x.kind, IssueKind::FrameOutOfRange(_)))
+                .count()
+        };
+        assert_eq!(out_of_range(counts[0]), 0);
+        assert!(out_of_range(9300) > 0);
+    }
 }
@@ -0,0 +1,198 @@
+use super::qualified::*;
+use super::*;
+
+use diva_db::bone::*;
+use diva_db::mot::MotionSetDatabase;
+
+///Lengths of both links of the IK chain starting at the bone `name`, see `bone_positions`
+pub(crate) fn chain_links(skel: &Skeleton, name: &str) -> Option<(f32, f32)> {
+    let index = skel.bones.iter().position(|x| x.name == name)?;
+    match (skel.bones[index].mode, bone_positions(skel)[index]) {
+        (BoneType::Type5, &[_, a, b]) | (BoneType::Type6, &[_, a, b]) => {
+            let len = |(x, y, z): (f32, f32, f32)| (x * x + y * y + z * z).sqrt();
+            Some((len(a), len(b)))
+        }
+        _ => None,
+    }
+}
+
+fn chain_length(skel: &Skeleton, name: &str) -> Option<f32> {
+    chain_links(skel, name).map(|(a, b)| a + b)
+}
+
+//How much longer the chain is on `to`, 1 if either skeleton lacks it
+fn chain_ratio(from: &Skeleton, to: &Skeleton, name: &str) -> f32 {
+    match (chain_length(from, name), chain_length(to, name)) {
+        (Some(a), Some(b)) if a > 0. => b / a,
+        _ => 1.,
+    }
+}
+
+impl FrameData {
+    fn scale(&mut self, factor: f32) {
+        use FrameData::*;
+        match self {
+            None => (),
+            Pose(p) => *p *= factor,
+            Linear(l) => l.iter_mut().for_each(|k| k.value *= factor),
+            Smooth(l) => {
+                for k in l {
+                    k.keyframe.value *= factor;
+                    k.interpolation *= factor;
+                }
+            }
+        }
+    }
+}
+
+impl Vec3 {
+    fn scale(&mut self, factor: f32) {
+        for set in self.sets_mut().iter_mut() {
+            set.scale(factor);
+        }
+    }
+}
+
+impl QualifiedMotion {
+    ///Adapts a motion made for the skeleton `from` to the skeleton `to`
+    ///
+    ///Translations of the root bones (`gblctr` and `n_hara_cp`) are scaled by the ratio of the
+    ///leg lengths. The IK chains are then posed on `from` and solved again on `to`, so their
+    ///targets move to where the same pose reaches with the new skeleton's bones, see `to_ik`.
+    ///Other bones are left untouched. Fails with the name of the first FK bone of an IK chain
+    ///missing from `mot_db`, leaving the motion as is
+    pub fn retarget(
+        &mut self,
+        mot_db: &MotionSetDatabase,
+        from: &Skeleton,
+        to: &Skeleton,
+    ) -> Result<(), String> {
+        let leg = (chain_ratio(from, to, "cl_momo_l") + chain_ratio(from, to, "cl_momo_r")) / 2.;
+        let mut mot = self.clone();
+        for (id, anim) in mot.anims.iter_mut() {
+            if !matches!(&mot_db.bones[*id][..], "gblctr" | "n_hara_cp") {
+                continue;
+            }
+            match anim {
+                Some(BoneAnim::Position(position))
+                | Some(BoneAnim::PositionRotation { position, .. }) => position.scale(leg),
+                _ => (),
+            }
+        }
+        mot.resolve_ik(mot_db, from, to)?;
+        *self = mot;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use cgmath::{InnerSpace, Vector3};
+
+    fn skeleton(thigh: f32, shin: f32) -> Skeleton<'static> {
+        let bone = |name: &str, mode| Bone {
+            mode,
+            name: name.into(),
+            ..Default::default()
+        };
+        Skeleton {
+            bones: vec![
+                bone("n_hara_cp", BoneType::Position),
+                bone("kl_kubi", BoneType::Rotation),
+                bone("cl_momo_l", BoneType::Type6),
+                bone("cl_momo_r", BoneType::Type6),
+            ],
+            motion_bone_names: vec![
+                "n_hara_cp".into(),
+                "kl_kubi".into(),
+                "cl_momo_l".into(),
+                "j_momo_l_wj".into(),
+                "j_sune_l_wj".into(),
+                "cl_momo_r".into(),
+                "j_momo_r_wj".into(),
+                "j_sune_r_wj".into(),
+            ],
+            positions: vec![
+                (0., 1., 0.),
+                (0., 0.1, 0.),
+                (0.1, 0., 0.),
+                (thigh, 0., 0.),
+                (shin, 0., 0.),
+                (-0.1, 0., 0.),
+                (thigh, 0., 0.),
+                (shin, 0., 0.),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn retarget_legs() {
+        let pose = |v| Vec3 {
+            x: FrameData::Pose(v),
+            ..Vec3::ZERO
+        };
+        //The left ankle 0.6 under its hip, with the knee bent
+        let ankle = Vec3 {
+            x: FrameData::Pose(0.1),
+            y: FrameData::Pose(-0.6),
+            z: FrameData::None,
+        };
+        let mut mot = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(pose(1.)))),
+                (bone_id("e_mune_cp"), Some(BoneAnim::Position(pose(1.)))),
+                (
+                    bone_id("cl_momo_l"),
+                    Some(BoneAnim::PositionIKRotation {
+                        position: ankle,
+                        target: Vec3::ZERO,
+                    }),
+                ),
+                (bone_id("kl_kubi"), Some(BoneAnim::Rotation(pose(1.)))),
+            ],
+        };
+        let from = skeleton(0.4, 0.4);
+        assert_eq!(chain_length(&from, "cl_momo_r"), Some(0.8));
+        mot.retarget(&mot_db(), &from, &skeleton(0.5, 0.5)).unwrap();
+        assert_eq!(mot.anims[0].1, Some(BoneAnim::Position(pose(1.25))));
+        //Only the root bones are moved
+        assert_eq!(mot.anims[1].1, Some(BoneAnim::Position(pose(1.))));
+        //The same bend reaches further down with the longer leg
+        match &mot.anims[2].1 {
+            Some(BoneAnim::PositionIKRotation { position, .. }) => {
+                let ankle = position.interpolate(0.);
+                assert!((ankle - Vector3::new(0.1, -0.75, 0.)).magnitude() < 1e-4)
+            }
+            e => panic!("unexpected anim {:?}", e),
+        }
+        assert_eq!(mot.anims[3].1, Some(BoneAnim::Rotation(pose(1.))));
+
+        let mut missing = mot.clone();
+        let db = MotionSetDatabase {
+            bones: mot_db().bones[..13].to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(
+            missing.retarget(&db, &from, &from),
+            Err("j_momo_l_wj".into())
+        );
+        assert_eq!(missing, mot);
+    }
+
+    #[test]
+    fn game_chain_links() {
+        let bone_db = bone_db();
+        let skel = &bone_db.skeletons[0];
+        let close =
+            |(a, b): (f32, f32), (x, y): (f32, f32)| (a - x).abs() < 1e-4 && (b - y).abs() < 1e-4;
+        assert!(close(
+            chain_links(skel, "cl_momo_l").unwrap(),
+            (0.39, 0.413)
+        ));
+        assert!(close(chain_links(skel, "c_kata_l").unwrap(), (0.208, 0.21)));
+        assert_eq!(chain_links(skel, "kl_kubi"), None);
+    }
+}
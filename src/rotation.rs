@@ -0,0 +1,194 @@
+//! Conversions between euler rotation curves and quaternions
+//!
+//! Rotation curves are euler angles in radians applied in Z * Y * X order. Blending or
+//! resampling them channel by channel breaks down around flips, so these helpers go through
+//! quaternion tracks with one quaternion per frame and back to continuous euler curves.
+use super::interpolate::bake;
+use super::qualified::*;
+use super::*;
+
+use cgmath::{InnerSpace, Matrix3, Quaternion, Rad, Rotation3, Vector3};
+
+use std::f32::consts::PI;
+
+///DIVA applies euler angles in Z * Y * X order
+pub fn euler_to_quat(x: f32, y: f32, z: f32) -> Quaternion<f32> {
+    Quaternion::from_angle_z(Rad(z))
+        * Quaternion::from_angle_y(Rad(y))
+        * Quaternion::from_angle_x(Rad(x))
+}
+
+///Inverse of `euler_to_quat`, returns (x, y, z)
+pub fn quat_to_euler(q: Quaternion<f32>) -> (f32, f32, f32) {
+    //cgmath matrices are column major, so `m.x.z` is row 2 column 0
+    let m = Matrix3::from(q);
+    let y = (-m.x.z).clamp(-1., 1.).asin();
+    let x = m.y.z.atan2(m.z.z);
+    let z = m.x.y.atan2(m.x.x);
+    (x, y, z)
+}
+
+///Shifts `angle` by whole turns so that it's the closest to `prev`
+pub fn unwrap_angle(prev: f32, angle: f32) -> f32 {
+    let turns = ((prev - angle) / (2. * PI)).round();
+    angle + turns * 2. * PI
+}
+
+///The euler angles equivalent to `euler` which are the closest to `prev`
+///
+///Besides whole turns on each axis, (x + π, π - y, z + π) describes the same rotation
+pub fn closest_euler(prev: Vector3<f32>, euler: Vector3<f32>) -> Vector3<f32> {
+    let unwrap = |v: Vector3<f32>| {
+        Vector3::new(
+            unwrap_angle(prev.x, v.x),
+            unwrap_angle(prev.y, v.y),
+            unwrap_angle(prev.z, v.z),
+        )
+    };
+    let a = unwrap(euler);
+    let b = unwrap(Vector3::new(euler.x + PI, PI - euler.y, euler.z + PI));
+    if (b - prev).magnitude2() < (a - prev).magnitude2() {
+        b
+    } else {
+        a
+    }
+}
+
+///Bakes quaternions into euler curves, each frame takes the angles closest to the previous one
+///starting from `reference`
+pub(crate) fn bake_rotation<F: FnMut(u16) -> Quaternion<f32>>(
+    len: u16,
+    reference: Vector3<f32>,
+    mut f: F,
+) -> Vec3 {
+    let mut prev = reference;
+    bake(len, |frame| {
+        let (x, y, z) = quat_to_euler(f(frame));
+        prev = closest_euler(prev, Vector3::new(x, y, z));
+        prev
+    })
+}
+
+///Samples the rotation curve on frames `0..len`
+///
+///Consecutive quaternions are kept in the same hemisphere so they can be interpolated directly
+pub fn to_quats(rotation: &Vec3, len: u16) -> Vec<Quaternion<f32>> {
+    let mut out: Vec<Quaternion<f32>> = vec![];
+    for frame in 0..len {
+        let e = rotation.interpolate(frame as f32);
+        let mut q = euler_to_quat(e.x, e.y, e.z);
+        if out.last().map(|p| p.dot(q) < 0.).unwrap_or(false) {
+            q = -q;
+        }
+        out.push(q);
+    }
+    out
+}
+
+///Turns a quaternion per frame back into continuous euler curves
+///
+///The first frame uses the angles in the (-π, π] range
+pub fn from_quats(quats: &[Quaternion<f32>]) -> Vec3 {
+    let first = quats
+        .first()
+        .map(|q| quat_to_euler(*q))
+        .map(|(x, y, z)| Vector3::new(x, y, z))
+        .unwrap_or_else(|| Vector3::new(0., 0., 0.));
+    bake_rotation(quats.len() as u16, first, |frame| quats[frame as usize])
+}
+
+impl FrameData {
+    ///Removes jumps of whole turns between consecutive keyframes of an angle
+    pub fn unwrap_angles(&mut self) {
+        use FrameData::*;
+        let mut prev: Option<f32> = Option::None;
+        let mut unwrap = |value: &mut f32| {
+            if let Some(p) = prev {
+                *value = unwrap_angle(p, *value);
+            }
+            prev = Some(*value);
+        };
+        match self {
+            None | Pose(_) => (),
+            Linear(l) => l.iter_mut().for_each(|k| unwrap(&mut k.value)),
+            Smooth(l) => l.iter_mut().for_each(|k| unwrap(&mut k.keyframe.value)),
+        }
+    }
+}
+
+impl Vec3 {
+    pub fn unwrap_angles(&mut self) {
+        for set in self.sets_mut().iter_mut() {
+            set.unwrap_angles();
+        }
+    }
+}
+
+impl QualifiedMotion {
+    ///Unwraps every rotation curve of the motion
+    pub fn unwrap_rotations(&mut self) {
+        for (_, anim) in self.anims.iter_mut() {
+            if let Some(anim) = anim {
+                for (name, v) in anim.curves_mut() {
+                    if name == "rotation" {
+                        v.unwrap_angles();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn euler_roundtrip() {
+        let (x, y, z) = quat_to_euler(euler_to_quat(0.3, -0.5, 1.2));
+        assert!((x - 0.3).abs() < 1e-5);
+        assert!((y + 0.5).abs() < 1e-5);
+        assert!((z - 1.2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn unwrap_flips() {
+        let mut set = FrameData::Linear(vec![
+            Keyframe {
+                frame: 0,
+                value: 3.,
+            },
+            Keyframe {
+                frame: 1,
+                value: -3.,
+            },
+        ]);
+        set.unwrap_angles();
+        assert!((set.interpolate(1.) - (2. * PI - 3.)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn quats_roundtrip() {
+        //Spins around Z past π, which flips the raw euler angles
+        let rot = Vec3 {
+            z: FrameData::Linear(vec![
+                Keyframe {
+                    frame: 0,
+                    value: 0.,
+                },
+                Keyframe {
+                    frame: 8,
+                    value: 4.,
+                },
+            ]),
+            ..Vec3::ZERO
+        };
+        let quats = to_quats(&rot, 9);
+        assert!(quats.windows(2).all(|w| w[0].dot(w[1]) > 0.));
+        let back = from_quats(&quats);
+        for frame in 0..9 {
+            let d = back.interpolate(frame as f32) - rot.interpolate(frame as f32);
+            assert!(d.magnitude() < 1e-4);
+        }
+    }
+}
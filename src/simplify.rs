@@ -0,0 +1,283 @@
+use super::interpolate::hermite;
+use super::qualified::*;
+use super::*;
+
+impl FrameData {
+    ///Removes keyframes while keeping the curve within `tolerance` of the original
+    ///
+    ///`Linear` sets are reduced with Ramer-Douglas-Peucker, constant sets become a `Pose` and
+    ///sets which stay at zero become `None`. `Smooth` sets only get collapsed when constant
+    pub fn simplify(&mut self, tolerance: f32) {
+        use FrameData::*;
+        let (min, max) = match self {
+            None => return,
+            Pose(p) => (*p, *p),
+            Linear(l) if l.is_empty() => return,
+            Linear(l) => value_range(l.iter().map(|x| x.value)),
+            Smooth(l) if l.is_empty() => return,
+            //The tangents also have to be flat for the curve to be constant
+            Smooth(l) if l.iter().any(|x| x.interpolation.abs() > f32::EPSILON) => {
+                (f32::NEG_INFINITY, f32::INFINITY)
+            }
+            Smooth(l) => value_range(l.iter().map(|x| x.keyframe.value)),
+        };
+        if max - min <= 2. * tolerance {
+            let pose = (min + max) / 2.;
+            *self = if pose.abs() <= tolerance {
+                None
+            } else {
+                Pose(pose)
+            };
+            return;
+        }
+        if let Linear(l) = self {
+            let mut keep = vec![false; l.len()];
+            keep[0] = true;
+            keep[l.len() - 1] = true;
+            split(l, &mut keep, tolerance, &line_error);
+            let mut keep = keep.into_iter();
+            l.retain(|_| keep.next().unwrap_or(true));
+        }
+    }
+}
+
+impl FrameData {
+    ///Converts a dense `Linear` set into `Smooth` keyframes within `tolerance` of the original
+    ///
+    ///Tangents are estimated from the neighbouring keyframes, and keyframes are added where the
+    ///hermite curve strays the furthest until it's within `tolerance` on every frame, as far as
+    ///the original keyframes allow. Keyframes repeating a frame are kept as a step
+    pub fn fit_smooth(&mut self, tolerance: f32) {
+        let keys = match self {
+            FrameData::Linear(l) if !l.is_empty() => l,
+            _ => return,
+        };
+        let last = keys.len() - 1;
+        //Keyframes repeating a frame make a step, so their tangent only follows the other side
+        let tangent = |i: usize| {
+            let prev = match i {
+                0 => i,
+                i if keys[i - 1].frame == keys[i].frame => i,
+                i => i - 1,
+            };
+            let next = match i {
+                i if i == last || keys[i + 1].frame == keys[i].frame => i,
+                i => i + 1,
+            };
+            match keys[next].frame - keys[prev].frame {
+                0 => 0.,
+                len => (keys[next].value - keys[prev].value) / len as f32,
+            }
+        };
+        let fitted: Vec<InterpKeyframe> = (0..keys.len())
+            .map(|i| InterpKeyframe {
+                keyframe: keys[i].clone(),
+                interpolation: tangent(i),
+            })
+            .collect();
+        let mut keep = vec![false; fitted.len()];
+        keep[0] = true;
+        keep[last] = true;
+        split(&fitted, &mut keep, tolerance, &hermite_error);
+        let mut keep = keep.into_iter();
+        let fitted = fitted
+            .into_iter()
+            .filter(|_| keep.next().unwrap_or(true))
+            .collect();
+        *self = FrameData::Smooth(fitted);
+        self.simplify(tolerance);
+    }
+}
+
+//Marks the keyframes of `keys` needed to stay within `tolerance` of the original curve
+//
+//`error` measures how far a segment strays from the original when only its ends are kept, and
+//returns it with the inner keyframe the closest to where it strays the furthest. Segments are
+//split there until they're all within `tolerance`
+fn split<K, F>(keys: &[K], keep: &mut [bool], tolerance: f32, error: &F)
+where
+    F: Fn(&[K]) -> (usize, f32),
+{
+    if keys.len() < 3 {
+        return;
+    }
+    let (idx, max) = error(keys);
+    if max > tolerance {
+        keep[idx] = true;
+        split(&keys[..=idx], &mut keep[..=idx], tolerance, error);
+        split(&keys[idx..], &mut keep[idx..], tolerance, error);
+    }
+}
+
+//Error of the line between the ends, measured on the keyframes
+fn line_error(keys: &[Keyframe]) -> (usize, f32) {
+    let (first, last) = (&keys[0], &keys[keys.len() - 1]);
+    let len = (last.frame - first.frame) as f32;
+    let error = |k: &Keyframe| {
+        let t = if len > 0. {
+            (k.frame - first.frame) as f32 / len
+        } else {
+            1.
+        };
+        (first.value + (last.value - first.value) * t - k.value).abs()
+    };
+    keys[1..keys.len() - 1]
+        .iter()
+        .map(error)
+        .enumerate()
+        .fold(
+            (0, 0.),
+            |acc, (i, e)| if e > acc.1 { (i + 1, e) } else { acc },
+        )
+}
+
+//Error of the hermite curve between the ends, measured on every frame against the lines
+//between the keyframes
+fn hermite_error(keys: &[InterpKeyframe]) -> (usize, f32) {
+    let (first, last) = (&keys[0], &keys[keys.len() - 1]);
+    let curve = |frame: u16| match last.keyframe.frame > first.keyframe.frame {
+        true => hermite(first, last, frame as f32),
+        false => last.keyframe.value,
+    };
+    let mut worst = (1, 0.);
+    for (i, pair) in keys.windows(2).enumerate() {
+        let (a, b) = (&pair[0].keyframe, &pair[1].keyframe);
+        for frame in a.frame..=b.frame {
+            let t = match b.frame - a.frame {
+                0 => 1.,
+                len => (frame - a.frame) as f32 / len as f32,
+            };
+            let error = (curve(frame) - (a.value + (b.value - a.value) * t)).abs();
+            if error > worst.1 {
+                let closest = if frame - a.frame <= b.frame - frame {
+                    i
+                } else {
+                    i + 1
+                };
+                worst = (closest.clamp(1, keys.len() - 2), error);
+            }
+        }
+    }
+    worst
+}
+
+fn value_range<I: Iterator<Item = f32>>(values: I) -> (f32, f32) {
+    values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), x| {
+        (min.min(x), max.max(x))
+    })
+}
+
+impl Vec3 {
+    pub fn simplify(&mut self, tolerance: f32) {
+        for set in self.sets_mut().iter_mut() {
+            set.simplify(tolerance);
+        }
+    }
+
+    pub fn fit_smooth(&mut self, tolerance: f32) {
+        for set in self.sets_mut().iter_mut() {
+            set.fit_smooth(tolerance);
+        }
+    }
+}
+
+impl QualifiedMotion {
+    ///Simplifies every set of the motion, see `FrameData::simplify`
+    pub fn simplify(&mut self, tolerance: f32) {
+        self.for_each_curve(|v| v.simplify(tolerance));
+    }
+
+    ///Fits smooth curves to every `Linear` set of the motion, see `FrameData::fit_smooth`
+    pub fn fit_smooth(&mut self, tolerance: f32) {
+        self.for_each_curve(|v| v.fit_smooth(tolerance));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn linear(values: &[f32]) -> FrameData {
+        let keys = values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| Keyframe {
+                frame: i as u16,
+                value,
+            })
+            .collect();
+        FrameData::Linear(keys)
+    }
+
+    #[test]
+    fn simplify_linear() {
+        let mut set = linear(&[0., 1., 2., 3., 2.01, 1., 0.]);
+        set.simplify(0.05);
+        assert_eq!(set.keyframes(), vec![0, 3, 6]);
+        let mut set = linear(&[0., 1., 2., 3., 2.01, 1., 0.]);
+        set.simplify(0.);
+        assert_eq!(set.keyframes(), vec![0, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn simplify_constant() {
+        let mut set = linear(&[1., 1.01, 0.99, 1.]);
+        set.simplify(0.02);
+        assert_eq!(set, FrameData::Pose(1.));
+        let mut set = linear(&[0., 0.001, 0.]);
+        set.simplify(0.01);
+        assert_eq!(set, FrameData::None);
+    }
+
+    #[test]
+    fn fit_smooth() {
+        let values: Vec<f32> = (0..=60).map(|x| (x as f32 / 10.).sin()).collect();
+        let mut set = linear(&values);
+        let dense = set.clone();
+        set.fit_smooth(0.001);
+        let keys = match &set {
+            FrameData::Smooth(l) => l.len(),
+            e => panic!("expected a smooth set, got {:?}", e),
+        };
+        assert!(keys < 15, "{} keys left", keys);
+        for frame in 0..=60 {
+            let frame = frame as f32;
+            assert!((set.interpolate(frame) - dense.interpolate(frame)).abs() <= 0.001);
+        }
+    }
+
+    #[test]
+    fn fit_smooth_between_keys() {
+        //Sparse keys, where the curve only strays away from them between the keyframes
+        let keys = [(0, 0.), (1, 0.), (20, 1.), (21, 1.)];
+        let keys = keys
+            .iter()
+            .map(|&(frame, value)| Keyframe { frame, value })
+            .collect();
+        let mut set = FrameData::Linear(keys);
+        let dense = set.clone();
+        set.fit_smooth(0.01);
+        for frame in 0..=21 {
+            let frame = frame as f32;
+            assert!((set.interpolate(frame) - dense.interpolate(frame)).abs() <= 0.01);
+        }
+    }
+
+    #[test]
+    fn fit_smooth_step() {
+        let keys = [(0, 0.), (5, 0.), (5, 1.), (10, 1.)];
+        let keys = keys
+            .iter()
+            .map(|&(frame, value)| Keyframe { frame, value })
+            .collect();
+        let mut set = FrameData::Linear(keys);
+        let dense = set.clone();
+        set.fit_smooth(0.001);
+        for frame in 0..=10 {
+            let frame = frame as f32;
+            let value = set.interpolate(frame);
+            assert!(value.is_finite());
+            assert!((value - dense.interpolate(frame)).abs() <= 0.001);
+        }
+    }
+}
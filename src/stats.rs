@@ -0,0 +1,181 @@
+//! Size and keyframe statistics of a motion, to budget motions and spot bloated imports
+use super::diff::Channel;
+use super::qualified::*;
+use super::read::SetType;
+use super::*;
+
+use std::cmp::Reverse;
+use std::io::Cursor;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelStats {
+    pub channel: Channel,
+    pub keyframes: usize,
+    ///Smallest and largest value of the set's keyframes
+    pub min: f32,
+    pub max: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoneStats {
+    pub bone: usize,
+    pub keyframes: usize,
+    ///Bytes taken by the bone's sets
+    pub size: usize,
+    pub channels: Vec<ChannelStats>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MotionStats {
+    pub frame_count: u16,
+    ///Number of sets of each type, in `None`, `Pose`, `Linear`, `Smooth` order
+    pub set_types: Vec<(SetType, usize)>,
+    pub bones: Vec<BoneStats>,
+    ///Bytes of each section of the file
+    pub header_size: usize,
+    pub types_size: usize,
+    pub keysets_size: usize,
+    pub bones_size: usize,
+}
+
+impl MotionStats {
+    pub fn set_count(&self, ty: &SetType) -> usize {
+        self.set_types
+            .iter()
+            .find(|(t, _)| t == ty)
+            .map(|(_, n)| *n)
+            .unwrap_or(0)
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.header_size + self.types_size + self.keysets_size + self.bones_size
+    }
+
+    ///The `n` bones with the largest sets
+    pub fn most_expensive(&self, n: usize) -> Vec<&BoneStats> {
+        let mut bones: Vec<&BoneStats> = self.bones.iter().collect();
+        bones.sort_by_key(|b| Reverse(b.size));
+        bones.truncate(n);
+        bones
+    }
+}
+
+fn channel_stats(set: &FrameData, channel: Channel) -> ChannelStats {
+    use FrameData::*;
+    let values: Vec<f32> = match set {
+        None => vec![0.],
+        Pose(p) => vec![*p],
+        Linear(l) => l.iter().map(|k| k.value).collect(),
+        Smooth(l) => l.iter().map(|k| k.keyframe.value).collect(),
+    };
+    ChannelStats {
+        channel,
+        keyframes: set.keyframes().len(),
+        min: values.iter().cloned().fold(f32::INFINITY, f32::min),
+        max: values.iter().cloned().fold(f32::NEG_INFINITY, f32::max),
+    }
+}
+
+impl QualifiedMotion {
+    ///Gathers the statistics of the motion as it would be written by `write`
+    pub fn stats(&self) -> MotionStats {
+        let mut set_types = vec![
+            (SetType::None, 0),
+            (SetType::Pose, 0),
+            (SetType::Linear, 0),
+            (SetType::Smooth, 0),
+        ];
+        let mut set_count = 0usize;
+        let mut bones = vec![];
+        for (id, anim) in &self.anims {
+            let mut channels = vec![];
+            if let Some(anim) = anim {
+                for (curve, v) in anim.curves() {
+                    for (axis, set) in ['x', 'y', 'z'].iter().zip(v.sets().iter()) {
+                        set_types[set.get_bits() as usize].1 += 1;
+                        set_count += 1;
+                        let channel = Channel {
+                            bone: *id,
+                            curve,
+                            axis: *axis,
+                        };
+                        channels.push(channel_stats(set, channel));
+                    }
+                }
+            }
+            bones.push(BoneStats {
+                bone: *id,
+                keyframes: channels.iter().map(|c| c.keyframes).sum(),
+                size: 0,
+                channels,
+            });
+        }
+
+        //Mirrors the layout of `write`, the sets are padded according to their offset
+        let header_size = 36;
+        let types = set_count.div_ceil(4);
+        let types_size = types + (header_size + types) % 6;
+        let mut writer = Cursor::new(vec![]);
+        writer.set_position((header_size + types_size) as u64);
+        for (stats, (_, anim)) in bones.iter_mut().zip(&self.anims) {
+            if let Some(anim) = anim {
+                let begin = writer.position();
+                anim.write()(&mut writer).unwrap();
+                stats.size = (writer.position() - begin) as usize;
+            }
+        }
+        MotionStats {
+            frame_count: self.get_max_keyframe(),
+            set_types,
+            keysets_size: bones.iter().map(|b| b.size).sum(),
+            bones,
+            header_size,
+            types_size,
+            bones_size: self.anims.len() * 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stats_sizes() {
+        let rot = Vec3 {
+            x: FrameData::Linear(vec![
+                Keyframe {
+                    frame: 0,
+                    value: -1.,
+                },
+                Keyframe {
+                    frame: 9,
+                    value: 2.,
+                },
+            ]),
+            y: FrameData::Pose(0.5),
+            z: FrameData::None,
+        };
+        let mot = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(Vec3::ZERO))),
+                (1, Some(BoneAnim::Rotation(rot))),
+                (2, None),
+            ],
+        };
+        let stats = mot.stats();
+        assert_eq!(stats.frame_count, 10);
+        assert_eq!(stats.set_count(&SetType::Pose), 4);
+        assert_eq!(stats.set_count(&SetType::None), 1);
+        assert_eq!(stats.bones[1].keyframes, 2);
+        assert_eq!(stats.bones[1].channels[0].min, -1.);
+        assert_eq!(stats.bones[1].channels[0].max, 2.);
+        assert_eq!(stats.bones[0].size, 12);
+        assert_eq!(stats.most_expensive(1)[0].bone, 1);
+        assert_eq!(stats.bones_size, 6);
+
+        let mut out = Cursor::new(vec![]);
+        let len = mot.write(&mut out).unwrap();
+        assert_eq!(stats.total_size(), len);
+    }
+}
@@ -0,0 +1,45 @@
+//! Fixtures shared by the tests
+use diva_db::bone::BoneDatabase;
+use diva_db::mot::MotionSetDatabase;
+
+pub(crate) const BONE_DATA: &[u8] = include_bytes!("../assets/bone_data.bin");
+
+///Motion bones known to the tests' mot_db, ids are the positions in this list
+const BONES: &[&str] = &[
+    "gblctr",
+    "kl_kubi",
+    "e_mune_cp",
+    "n_hara",
+    "cl_momo_l",
+    "cl_momo_r",
+    "n_hara_cp",
+    "c_kata_l",
+    "c_kata_r",
+    "cl_kao",
+    "j_kata_l_wj_cu",
+    "j_ude_l_wj",
+    "j_kao_wj",
+    "kg_hara_y",
+    "kl_hara_xz",
+    "kl_kosi_y",
+    "kl_kosi_xz",
+    "j_momo_l_wj",
+    "j_sune_l_wj",
+];
+
+pub(crate) fn mot_db() -> MotionSetDatabase {
+    MotionSetDatabase {
+        bones: BONES.iter().map(|x| x.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+///Id of the motion bone `name` in `mot_db()`
+pub(crate) fn bone_id(name: &str) -> usize {
+    BONES.iter().position(|x| *x == name).unwrap()
+}
+
+///The game's bone database, the first skeleton is the common one
+pub(crate) fn bone_db() -> BoneDatabase<'static> {
+    BoneDatabase::read(BONE_DATA).unwrap().1
+}
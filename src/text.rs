@@ -0,0 +1,336 @@
+//! Line based text format for qualified motions
+//!
+//! Every bone is a block starting with `bone <name> <kind>`, followed by one line per set
+//! (`<curve>.<axis> <type> [value]`) and one indented line per keyframe (`frame value [tangent]`):
+//!
+//! ```text
+//! bone kl_kubi rotation
+//! rotation.x smooth
+//!     0 0 0.1
+//!     10 1 0
+//! rotation.y pose 0.5
+//! rotation.z none
+//! ```
+use super::qualified::*;
+use super::*;
+
+use diva_db::mot::MotionSetDatabase;
+
+use std::fmt::{self, Write};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TextError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for TextError {}
+
+const AXES: [&str; 3] = ["x", "y", "z"];
+
+impl BoneAnim {
+    fn text_kind(&self) -> &'static str {
+        use BoneAnim::*;
+        match self {
+            Rotation(_) => "rotation",
+            Type1(_, _) => "type1",
+            Position(_) => "position",
+            PositionRotation { .. } => "position_rotation",
+            RotationIK { .. } => "rotation_ik",
+            ArmIK { .. } => "arm_ik",
+            PositionIKRotation { .. } => "position_ik_rotation",
+        }
+    }
+
+    fn from_text_kind<F: FnMut(&str) -> Vec3>(kind: &str, mut vec3: F) -> Option<Self> {
+        use BoneAnim::*;
+        let anim = match kind {
+            "rotation" => Rotation(vec3("rotation")),
+            "type1" => Type1(vec3("unk0"), vec3("unk1")),
+            "position" => Position(vec3("position")),
+            "position_rotation" => PositionRotation {
+                position: vec3("position"),
+                rotation: vec3("rotation"),
+            },
+            "rotation_ik" => RotationIK {
+                target: vec3("target"),
+                rotation: vec3("rotation"),
+            },
+            "arm_ik" => ArmIK {
+                target: vec3("target"),
+                rotation: vec3("rotation"),
+            },
+            "position_ik_rotation" => PositionIKRotation {
+                position: vec3("position"),
+                target: vec3("target"),
+            },
+            _ => return None,
+        };
+        Some(anim)
+    }
+}
+
+fn write_set(out: &mut String, name: &str, set: &FrameData) -> fmt::Result {
+    use FrameData::*;
+    match set {
+        None => writeln!(out, "{} none", name),
+        Pose(p) => writeln!(out, "{} pose {}", name, p),
+        Linear(l) => {
+            writeln!(out, "{} linear", name)?;
+            for k in l {
+                writeln!(out, "    {} {}", k.frame, k.value)?;
+            }
+            Ok(())
+        }
+        Smooth(l) => {
+            writeln!(out, "{} smooth", name)?;
+            for k in l {
+                writeln!(
+                    out,
+                    "    {} {} {}",
+                    k.keyframe.frame, k.keyframe.value, k.interpolation
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+///Prints `mot` in the text format, bones are written by name
+pub fn to_text(mot: &QualifiedMotion, mot_db: &MotionSetDatabase) -> String {
+    let mut out = String::new();
+    for (i, (id, anim)) in mot.anims.iter().enumerate() {
+        if i != 0 {
+            out.push('\n');
+        }
+        let name = &mot_db.bones[*id];
+        let anim = match anim {
+            Some(a) => a,
+            None => {
+                out += &format!("bone {} none\n", name);
+                continue;
+            }
+        };
+        out += &format!("bone {} {}\n", name, anim.text_kind());
+        for (curve, v) in anim.curves() {
+            for (axis, set) in AXES.iter().zip([&v.x, &v.y, &v.z].iter()) {
+                write_set(&mut out, &format!("{}.{}", curve, axis), set).unwrap();
+            }
+        }
+    }
+    out
+}
+
+struct Bone {
+    line: usize,
+    id: usize,
+    kind: String,
+    //Line, curve name (`rotation.x`) and its set
+    sets: Vec<(usize, String, FrameData)>,
+}
+
+impl Bone {
+    fn finish(mut self) -> Result<(usize, Option<BoneAnim>), TextError> {
+        if self.kind == "none" {
+            return Ok((self.id, None));
+        }
+        let sets = &mut self.sets;
+        let mut take = |name: String| {
+            sets.iter()
+                .position(|(_, n, _)| *n == name)
+                .map(|i| sets.remove(i).2)
+                .unwrap_or(FrameData::None)
+        };
+        let anim = BoneAnim::from_text_kind(&self.kind, |curve| Vec3 {
+            x: take(format!("{}.x", curve)),
+            y: take(format!("{}.y", curve)),
+            z: take(format!("{}.z", curve)),
+        });
+        let anim = match anim {
+            Some(a) => a,
+            None => return Err(error(self.line, format!("unknown kind `{}`", self.kind))),
+        };
+        if let Some((line, name, _)) = self.sets.first() {
+            let message = format!("`{}` isn't a curve of a {} bone", name, self.kind);
+            return Err(error(*line, message));
+        }
+        Ok((self.id, Some(anim)))
+    }
+}
+
+fn error(line: usize, message: String) -> TextError {
+    TextError { line, message }
+}
+
+fn parse_num<T: std::str::FromStr>(
+    line: usize,
+    s: Option<&str>,
+    what: &str,
+) -> Result<T, TextError> {
+    match s {
+        Some(s) => s
+            .parse()
+            .map_err(|_| error(line, format!("invalid {} `{}`", what, s))),
+        None => Err(error(line, format!("missing {}", what))),
+    }
+}
+
+///Parses the text format, bone names are looked up in `mot_db`
+///
+///Curves left out of a bone block are `FrameData::None`, lines starting with `#` are ignored
+pub fn from_text(text: &str, mot_db: &MotionSetDatabase) -> Result<QualifiedMotion, TextError> {
+    let mut anims = vec![];
+    let mut bone: Option<Bone> = None;
+    for (n, raw) in text.lines().enumerate() {
+        let n = n + 1;
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let first = words.next().unwrap_or_default();
+        if first == "bone" {
+            if let Some(b) = bone.take() {
+                anims.push(b.finish()?);
+            }
+            let name = words
+                .next()
+                .ok_or_else(|| error(n, "missing bone name".into()))?;
+            let id = mot_db
+                .bones
+                .iter()
+                .position(|x| x[..] == *name)
+                .ok_or_else(|| error(n, format!("unknown bone `{}`", name)))?;
+            let kind = words
+                .next()
+                .ok_or_else(|| error(n, "missing bone kind".into()))?;
+            bone = Some(Bone {
+                line: n,
+                id,
+                kind: kind.to_string(),
+                sets: vec![],
+            });
+            continue;
+        }
+        let bone = bone
+            .as_mut()
+            .ok_or_else(|| error(n, "expected a `bone` line".into()))?;
+        //Keyframes are the only indented lines
+        if raw.starts_with(char::is_whitespace) {
+            let set = bone.sets.last_mut().map(|(_, _, s)| s);
+            let frame = parse_num(n, Some(first), "frame")?;
+            let value = parse_num(n, words.next(), "value")?;
+            let last = match set {
+                Some(FrameData::Linear(l)) => {
+                    l.push(Keyframe { frame, value });
+                    l.iter().rev().nth(1).map(|x| x.frame)
+                }
+                Some(FrameData::Smooth(l)) => {
+                    let interpolation = parse_num(n, words.next(), "tangent")?;
+                    l.push(InterpKeyframe {
+                        keyframe: Keyframe { frame, value },
+                        interpolation,
+                    });
+                    l.iter().rev().nth(1).map(|x| x.keyframe.frame)
+                }
+                _ => {
+                    return Err(error(
+                        n,
+                        "keyframe outside of a linear or smooth set".into(),
+                    ))
+                }
+            };
+            if last.map(|x| x >= frame).unwrap_or(false) {
+                return Err(error(
+                    n,
+                    format!("frame {} isn't after the previous one", frame),
+                ));
+            }
+        } else {
+            let set = match words.next() {
+                Some("none") => FrameData::None,
+                Some("pose") => FrameData::Pose(parse_num(n, words.next(), "pose value")?),
+                Some("linear") => FrameData::Linear(vec![]),
+                Some("smooth") => FrameData::Smooth(vec![]),
+                Some(e) => return Err(error(n, format!("unknown set type `{}`", e))),
+                None => return Err(error(n, "missing set type".into())),
+            };
+            if bone.sets.iter().any(|(_, name, _)| name == first) {
+                return Err(error(n, format!("`{}` is defined twice", first)));
+            }
+            bone.sets.push((n, first.to_string(), set));
+        }
+        if let Some(extra) = words.next() {
+            return Err(error(n, format!("unexpected `{}`", extra)));
+        }
+    }
+    if let Some(b) = bone {
+        anims.push(b.finish()?);
+    }
+    Ok(QualifiedMotion { anims })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn text_roundtrip() {
+        let rot = Vec3 {
+            x: FrameData::Smooth(vec![
+                InterpKeyframe {
+                    keyframe: Keyframe {
+                        frame: 0,
+                        value: 0.,
+                    },
+                    interpolation: 0.1,
+                },
+                InterpKeyframe {
+                    keyframe: Keyframe {
+                        frame: 10,
+                        value: 1.,
+                    },
+                    interpolation: 0.,
+                },
+            ]),
+            y: FrameData::Pose(0.5),
+            z: FrameData::None,
+        };
+        let pos = Vec3 {
+            x: FrameData::Linear(vec![Keyframe {
+                frame: 2,
+                value: -0.125,
+            }]),
+            ..Vec3::ZERO
+        };
+        let mot = QualifiedMotion {
+            anims: vec![
+                (1, Some(BoneAnim::Rotation(rot))),
+                (0, Some(BoneAnim::Position(pos))),
+                (2, None),
+            ],
+        };
+        let text = to_text(&mot, &mot_db());
+        assert!(text.starts_with("bone kl_kubi rotation\nrotation.x smooth\n    0 0 0.1\n"));
+        assert_eq!(from_text(&text, &mot_db()), Ok(mot));
+    }
+
+    #[test]
+    fn text_errors() {
+        let text = "bone kl_kubi rotation\nrotation.x linear\n    5 1\n    3 2\n";
+        assert_eq!(from_text(text, &mot_db()).unwrap_err().line, 4);
+        let text = "bone kl_kubi rotation\nposition.x pose 1\n";
+        assert_eq!(from_text(text, &mot_db()).unwrap_err().line, 2);
+        let text = "bone n_hoge rotation\n";
+        assert_eq!(from_text(text, &mot_db()).unwrap_err().line, 1);
+        let text = "bone kl_kubi rotation\nrotation.x pose abc\n";
+        assert_eq!(from_text(text, &mot_db()).unwrap_err().line, 2);
+    }
+}
@@ -0,0 +1,348 @@
+use super::qualified::*;
+use super::*;
+
+use std::fmt;
+
+///A time scale factor which is zero, negative or not finite
+#[derive(Debug, PartialEq, Clone)]
+pub struct TimeScaleError {
+    pub factor: f32,
+}
+
+impl fmt::Display for TimeScaleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "can't scale time by {}", self.factor)
+    }
+}
+
+impl std::error::Error for TimeScaleError {}
+
+fn check_factor(factor: f32) -> Result<(), TimeScaleError> {
+    if factor.is_finite() && factor > 0. {
+        Ok(())
+    } else {
+        Err(TimeScaleError { factor })
+    }
+}
+
+fn scale_frame(frame: u16, factor: f32) -> u16 {
+    (frame as f32 * factor).round().clamp(0., u16::MAX as f32) as u16
+}
+
+impl FrameData {
+    ///Stretches the set in time by `factor`
+    ///
+    ///`Linear` sets are resampled at their retimed keyframes, `Smooth` sets keep their keyframes
+    ///with retimed frames and tangents scaled to match. Keyframes which round to the same frame
+    ///are merged. `factor` has to be finite and positive
+    pub fn scale_time(&mut self, factor: f32) -> Result<(), TimeScaleError> {
+        check_factor(factor)?;
+        self.scale_keys(factor);
+        Ok(())
+    }
+
+    fn scale_keys(&mut self, factor: f32) {
+        use FrameData::*;
+        match self {
+            None | Pose(_) => (),
+            Linear(l) => {
+                let set = Linear(l.clone());
+                for k in l.iter_mut() {
+                    k.frame = scale_frame(k.frame, factor);
+                    k.value = set.interpolate(k.frame as f32 / factor);
+                }
+                l.dedup_by_key(|k| k.frame);
+            }
+            Smooth(l) => {
+                for k in l.iter_mut() {
+                    k.keyframe.frame = scale_frame(k.keyframe.frame, factor);
+                    k.interpolation /= factor;
+                }
+                l.dedup_by_key(|k| k.keyframe.frame);
+            }
+        }
+    }
+}
+
+impl FrameData {
+    ///Keeps the `start..=end` range of the set and moves it to start at frame 0
+    ///
+    ///Keyframes are inserted on both ends from the evaluated curve, so the shape is preserved
+    pub fn trim(&mut self, start: u16, end: u16) {
+        use FrameData::*;
+        let (s, e) = (start as f32, end as f32);
+        let (first, last) = (self.interpolate(s), self.interpolate(e));
+        let (first_slope, last_slope) = (self.tangents(s).1, self.tangents(e).0);
+        let inside = |frame: u16| frame > start && frame < end;
+        match self {
+            None | Pose(_) => (),
+            Linear(l) => {
+                let mut keys = vec![Keyframe {
+                    frame: start,
+                    value: first,
+                }];
+                keys.extend(l.iter().filter(|k| inside(k.frame)).cloned());
+                if end > start {
+                    keys.push(Keyframe {
+                        frame: end,
+                        value: last,
+                    });
+                }
+                keys.iter_mut().for_each(|k| k.frame -= start);
+                *l = keys;
+            }
+            Smooth(l) => {
+                let key = |frame, value, interpolation| InterpKeyframe {
+                    keyframe: Keyframe { frame, value },
+                    interpolation,
+                };
+                let mut keys = vec![key(start, first, first_slope)];
+                keys.extend(l.iter().filter(|k| inside(k.keyframe.frame)).cloned());
+                if end > start {
+                    keys.push(key(end, last, last_slope));
+                }
+                keys.iter_mut().for_each(|k| k.keyframe.frame -= start);
+                *l = keys;
+            }
+        }
+    }
+
+    ///Moves every keyframe by `frames`, anything moved before frame 0 is trimmed off
+    ///
+    ///Keyframes pushed past the last frame are merged into a single one at `u16::MAX`
+    pub fn offset(&mut self, frames: i32) {
+        use FrameData::*;
+        let keys = self.keyframes();
+        let last = match keys.last() {
+            Some(l) => *l,
+            _ => return,
+        };
+        if frames < 0 {
+            let start = (-frames).min(u16::MAX as i32) as u16;
+            if start > last {
+                *self = Pose(self.interpolate(last as f32));
+            } else {
+                self.trim(start, last);
+            }
+            return;
+        }
+        let shift = |frame: u16| (frame as i32 + frames).min(u16::MAX as i32) as u16;
+        match self {
+            None | Pose(_) => (),
+            Linear(l) => {
+                l.iter_mut().for_each(|k| k.frame = shift(k.frame));
+                l.dedup_by_key(|k| k.frame);
+            }
+            Smooth(l) => {
+                l.iter_mut()
+                    .for_each(|k| k.keyframe.frame = shift(k.keyframe.frame));
+                l.dedup_by_key(|k| k.keyframe.frame);
+            }
+        }
+    }
+
+    ///Plays the set backwards, frame `end` becomes frame 0
+    ///
+    ///Keyframes after `end` are dropped
+    pub fn reverse(&mut self, end: u16) {
+        use FrameData::*;
+        match self {
+            None | Pose(_) => (),
+            Linear(l) => {
+                l.retain(|k| k.frame <= end);
+                l.reverse();
+                l.iter_mut().for_each(|k| k.frame = end - k.frame);
+            }
+            Smooth(l) => {
+                l.retain(|k| k.keyframe.frame <= end);
+                l.reverse();
+                for k in l.iter_mut() {
+                    k.keyframe.frame = end - k.keyframe.frame;
+                    k.interpolation = -k.interpolation;
+                }
+            }
+        }
+    }
+}
+
+impl Vec3 {
+    pub fn scale_time(&mut self, factor: f32) -> Result<(), TimeScaleError> {
+        check_factor(factor)?;
+        self.scale_keys(factor);
+        Ok(())
+    }
+
+    fn scale_keys(&mut self, factor: f32) {
+        for set in self.sets_mut().iter_mut() {
+            set.scale_keys(factor);
+        }
+    }
+
+    pub fn trim(&mut self, start: u16, end: u16) {
+        for set in self.sets_mut().iter_mut() {
+            set.trim(start, end);
+        }
+    }
+
+    pub fn offset(&mut self, frames: i32) {
+        for set in self.sets_mut().iter_mut() {
+            set.offset(frames);
+        }
+    }
+
+    pub fn reverse(&mut self, end: u16) {
+        for set in self.sets_mut().iter_mut() {
+            set.reverse(end);
+        }
+    }
+}
+
+impl QualifiedMotion {
+    ///Converts a motion keyed at `src_fps` to `dst_fps`
+    ///
+    ///Game motions run at `FPS`, so a 30fps BVH would be resampled with `resample(30., FPS)`.
+    ///Fails when either rate is zero, negative or not finite
+    pub fn resample(&mut self, src_fps: f32, dst_fps: f32) -> Result<(), TimeScaleError> {
+        self.scale_time(dst_fps / src_fps)
+    }
+
+    ///Stretches the motion in time by `factor`, see `FrameData::scale_time`
+    pub fn scale_time(&mut self, factor: f32) -> Result<(), TimeScaleError> {
+        check_factor(factor)?;
+        self.for_each_curve(|v| v.scale_keys(factor));
+        Ok(())
+    }
+
+    ///Cuts the motion down to `start..=end`, which becomes its new `0..=end - start` range
+    pub fn trim(&mut self, start: u16, end: u16) {
+        self.for_each_curve(|v| v.trim(start, end));
+    }
+
+    ///Delays the motion by `frames`, or trims its beginning off when negative
+    pub fn offset(&mut self, frames: i32) {
+        self.for_each_curve(|v| v.offset(frames));
+    }
+
+    ///Plays the motion backwards, keeping its frame count
+    pub fn reverse(&mut self) {
+        let end = self.get_max_keyframe().saturating_sub(1);
+        self.for_each_curve(|v| v.reverse(end));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resample_linear() {
+        let mut set = FrameData::Linear(vec![
+            Keyframe {
+                frame: 0,
+                value: 0.,
+            },
+            Keyframe {
+                frame: 2,
+                value: 1.,
+            },
+        ]);
+        set.scale_time(2.).unwrap();
+        assert_eq!(set.keyframes(), vec![0, 4]);
+        assert_eq!(set.interpolate(1.), 0.25);
+        set.scale_time(0.1).unwrap();
+        assert_eq!(set.keyframes(), vec![0]);
+    }
+
+    #[test]
+    fn invalid_factor() {
+        let mut set = smooth();
+        for &factor in &[0., -1., f32::INFINITY] {
+            assert_eq!(set.scale_time(factor), Err(TimeScaleError { factor }));
+        }
+        assert!(set.scale_time(f32::NAN).is_err());
+        assert_eq!(set, smooth());
+        let mut mot = QualifiedMotion {
+            anims: vec![(0, None)],
+        };
+        assert!(mot.resample(0., FPS).is_err());
+        assert!(mot.resample(30., FPS).is_ok());
+    }
+
+    #[test]
+    fn resample_smooth() {
+        let key = |frame, value, interpolation| InterpKeyframe {
+            keyframe: Keyframe { frame, value },
+            interpolation,
+        };
+        let mut set = FrameData::Smooth(vec![key(0, 0., 0.5), key(30, 1., 0.)]);
+        let original = set.clone();
+        set.scale_time(2.).unwrap();
+        assert_eq!(
+            set,
+            FrameData::Smooth(vec![key(0, 0., 0.25), key(60, 1., 0.)])
+        );
+        for frame in 0..30 {
+            let frame = frame as f32;
+            let diff = set.interpolate(frame * 2.) - original.interpolate(frame);
+            assert!(diff.abs() < 1e-5);
+        }
+    }
+
+    fn smooth() -> FrameData {
+        let key = |frame, value, interpolation| InterpKeyframe {
+            keyframe: Keyframe { frame, value },
+            interpolation,
+        };
+        FrameData::Smooth(vec![key(0, 0., 0.2), key(10, 1., -0.1), key(20, 0., 0.)])
+    }
+
+    #[test]
+    fn trim_smooth() {
+        let original = smooth();
+        let mut set = smooth();
+        set.trim(5, 15);
+        assert_eq!(set.keyframes(), vec![0, 5, 10]);
+        for frame in 5..=15 {
+            let diff = set.interpolate((frame - 5) as f32) - original.interpolate(frame as f32);
+            assert!(diff.abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn offset_and_reverse() {
+        let original = smooth();
+        let mut set = smooth();
+        set.offset(5);
+        assert_eq!(set.keyframes(), vec![5, 15, 25]);
+        set.offset(-5);
+        assert_eq!(set, original);
+
+        set.reverse(20);
+        for frame in 0..=20 {
+            let diff = set.interpolate((20 - frame) as f32) - original.interpolate(frame as f32);
+            assert!(diff.abs() < 1e-5);
+        }
+
+        let mut set = FrameData::Linear(vec![
+            Keyframe {
+                frame: u16::MAX - 1,
+                value: 0.,
+            },
+            Keyframe {
+                frame: u16::MAX,
+                value: 1.,
+            },
+        ]);
+        set.offset(10);
+        assert_eq!(set.keyframes(), vec![u16::MAX]);
+    }
+
+    #[test]
+    fn empty_motion() {
+        let mut mot = QualifiedMotion {
+            anims: vec![(0, None)],
+        };
+        mot.reverse();
+        assert_eq!(mot.anims, vec![(0, None)]);
+    }
+}
@@ -0,0 +1,327 @@
+//! Checks for motions which would be written incorrectly or crash the game
+use super::qualified::*;
+use super::*;
+
+use diva_db::bone::*;
+use diva_db::mot::MotionSetDatabase;
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IssueKind {
+    ///The keyframe isn't after the previous one
+    UnorderedKeyframe(u16),
+    ///The keyframe is at or past the motion's frame count
+    FrameOutOfRange(u16),
+    ///The set contains a NaN or infinite value
+    NonFinite,
+    ///`Linear` or `Smooth` without any keyframes
+    EmptySet,
+    ///Key counts are written as u16
+    TooManyKeyframes(usize),
+    ///The bones' types need another number of sets
+    SetCountMismatch { expected: usize, found: usize },
+    ///The animation doesn't match the type of the skeleton's bone
+    WrongBoneType(BoneType),
+    ///The bone id isn't in the motion database
+    BoneOutOfRange(usize),
+    ///The bone id is animated more than once
+    DuplicateBone(usize),
+    ///The bone database has no skeleton to check the bones against
+    NoSkeleton,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    ///The set or bone with the issue, e.g. `set 4` or `kl_kubi rotation.x`
+    pub location: String,
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use IssueKind::*;
+        match self {
+            UnorderedKeyframe(frame) => {
+                write!(f, "keyframe {} isn't after the previous one", frame)
+            }
+            FrameOutOfRange(frame) => write!(f, "frame {} is out of range", frame),
+            NonFinite => write!(f, "non finite value"),
+            EmptySet => write!(f, "set has no keyframes"),
+            TooManyKeyframes(n) => write!(f, "{} keyframes, at most {} fit", n, u16::MAX),
+            SetCountMismatch { expected, found } => {
+                write!(f, "bones need {} sets but there are {}", expected, found)
+            }
+            WrongBoneType(mode) => write!(f, "animation doesn't match bone type {:?}", mode),
+            BoneOutOfRange(id) => write!(f, "bone id {} isn't in the motion database", id),
+            DuplicateBone(id) => write!(f, "bone id {} appears more than once", id),
+            NoSkeleton => write!(f, "the bone database has no skeleton"),
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.kind)
+    }
+}
+
+impl FrameData {
+    fn issues(&self, frame_count: u16) -> Vec<IssueKind> {
+        use FrameData::*;
+        let values: Vec<f32> = match self {
+            None => vec![],
+            Pose(p) => vec![*p],
+            Linear(l) => l.iter().map(|k| k.value).collect(),
+            Smooth(l) => l
+                .iter()
+                .flat_map(|k| vec![k.keyframe.value, k.interpolation])
+                .collect(),
+        };
+        let frames = self.keyframes();
+        let mut out = vec![];
+        match self {
+            Linear(l) if l.is_empty() => out.push(IssueKind::EmptySet),
+            Smooth(l) if l.is_empty() => out.push(IssueKind::EmptySet),
+            _ => (),
+        }
+        if frames.len() > u16::MAX as usize {
+            out.push(IssueKind::TooManyKeyframes(frames.len()));
+        }
+        if values.iter().any(|x| !x.is_finite()) {
+            out.push(IssueKind::NonFinite);
+        }
+        for w in frames.windows(2) {
+            if w[1] <= w[0] {
+                out.push(IssueKind::UnorderedKeyframe(w[1]));
+            }
+        }
+        if let Some(frame) = frames.iter().find(|x| **x >= frame_count) {
+            out.push(IssueKind::FrameOutOfRange(*frame));
+        }
+        out
+    }
+}
+
+//Issues of the bone ids which don't depend on the animations
+fn bone_issues<'a, I: Iterator<Item = &'a usize>>(
+    ids: I,
+    mot_db: &MotionSetDatabase,
+    bone_db: &BoneDatabase,
+) -> Vec<Issue> {
+    let mut out = vec![];
+    if bone_db.skeletons.is_empty() {
+        out.push(Issue {
+            location: "bone_db".into(),
+            kind: IssueKind::NoSkeleton,
+        });
+    }
+    let mut seen = vec![];
+    for id in ids {
+        if seen.contains(id) {
+            let location = match mot_db.bones.get(*id) {
+                Some(name) => name.clone(),
+                None => format!("bone {}", id),
+            };
+            out.push(Issue {
+                location,
+                kind: IssueKind::DuplicateBone(*id),
+            });
+        }
+        seen.push(*id);
+    }
+    out
+}
+
+fn set_count(mode: BoneType) -> usize {
+    match mode {
+        BoneType::Rotation | BoneType::Position => 3,
+        _ => 6,
+    }
+}
+
+fn matches_type(anim: &BoneAnim, mode: BoneType) -> bool {
+    use BoneAnim::*;
+    matches!(
+        (anim, mode),
+        (Rotation(_), BoneType::Rotation)
+            | (Type1(..), BoneType::Type1)
+            | (Position(_), BoneType::Position)
+            | (PositionRotation { .. }, BoneType::Type3)
+            | (RotationIK { .. }, BoneType::Type4)
+            | (ArmIK { .. }, BoneType::Type5)
+            | (PositionIKRotation { .. }, BoneType::Type6)
+    )
+}
+
+impl Motion {
+    ///Checks every set of the motion on its own, keys have to be before `frame_count`
+    ///
+    ///The frame count is stored in the motion's header, see `Motion::parse_frame_counts`
+    pub fn validate(&self, frame_count: u16) -> Vec<Issue> {
+        let mut out = vec![];
+        for (i, set) in self.sets.iter().enumerate() {
+            out.extend(set.issues(frame_count).into_iter().map(|kind| Issue {
+                location: format!("set {}", i),
+                kind,
+            }));
+        }
+        out
+    }
+
+    ///Same as `validate`, also checks the bones against the databases so that `qualify` can't
+    ///fail
+    pub fn validate_with<'a>(
+        &self,
+        frame_count: u16,
+        mot_db: &MotionSetDatabase,
+        bone_db: &BoneDatabase<'a>,
+    ) -> Vec<Issue> {
+        let mut out = self.validate(frame_count);
+        out.extend(bone_issues(self.bones.iter(), mot_db, bone_db));
+        let mut expected = 0;
+        for (i, id) in self.bones.iter().enumerate() {
+            match mot_db.bones.get(*id) {
+                Some(name) => {
+                    expected += find_bone(name, bone_db)
+                        .map(|b| set_count(b.mode))
+                        .unwrap_or(0)
+                }
+                None => out.push(Issue {
+                    location: format!("bone {}", i),
+                    kind: IssueKind::BoneOutOfRange(*id),
+                }),
+            }
+        }
+        //Files end with a terminating set, so only missing sets are an issue
+        if self.sets.len() < expected {
+            out.push(Issue {
+                location: "motion".into(),
+                kind: IssueKind::SetCountMismatch {
+                    expected,
+                    found: self.sets.len(),
+                },
+            });
+        }
+        out
+    }
+}
+
+impl QualifiedMotion {
+    ///Checks every curve of the motion and that its bones match the databases
+    ///
+    ///Keys have to be before `frame_count`. An empty list means the motion can be written safely
+    pub fn validate<'a>(
+        &self,
+        frame_count: u16,
+        mot_db: &MotionSetDatabase,
+        bone_db: &BoneDatabase<'a>,
+    ) -> Vec<Issue> {
+        let mut out = bone_issues(self.anims.iter().map(|(id, _)| id), mot_db, bone_db);
+        for (id, anim) in &self.anims {
+            let name = match mot_db.bones.get(*id) {
+                Some(n) => n,
+                None => {
+                    out.push(Issue {
+                        location: format!("bone {}", id),
+                        kind: IssueKind::BoneOutOfRange(*id),
+                    });
+                    continue;
+                }
+            };
+            let anim = match anim {
+                Some(a) => a,
+                None => continue,
+            };
+            if let Some(bone) = find_bone(name, bone_db) {
+                if !matches_type(anim, bone.mode) {
+                    out.push(Issue {
+                        location: name.clone(),
+                        kind: IssueKind::WrongBoneType(bone.mode),
+                    });
+                }
+            }
+            for (curve, v) in anim.curves() {
+                for (axis, set) in ["x", "y", "z"].iter().zip(v.sets().iter()) {
+                    out.extend(set.issues(frame_count).into_iter().map(|kind| Issue {
+                        location: format!("{} {}.{}", name, curve, axis),
+                        kind,
+                    }));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn validate_sets() {
+        let mot = Motion {
+            sets: vec![
+                FrameData::Linear(vec![]),
+                FrameData::Pose(f32::NAN),
+                FrameData::Linear(vec![
+                    Keyframe {
+                        frame: 3,
+                        value: 0.,
+                    },
+                    Keyframe {
+                        frame: 3,
+                        value: 1.,
+                    },
+                ]),
+            ],
+            bones: vec![1, 70],
+        };
+        let kinds: Vec<IssueKind> = mot.validate(3).into_iter().map(|x| x.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                IssueKind::EmptySet,
+                IssueKind::NonFinite,
+                IssueKind::UnorderedKeyframe(3),
+                IssueKind::FrameOutOfRange(3),
+            ]
+        );
+        assert_eq!(mot.validate(4).len(), 3);
+        let issues = mot.validate_with(4, &mot_db(), &bone_db());
+        assert_eq!(issues[3].kind, IssueKind::BoneOutOfRange(70));
+        assert_eq!(issues.len(), 4);
+    }
+
+    #[test]
+    fn validate_qualified() {
+        let mot = QualifiedMotion {
+            anims: vec![
+                (0, Some(BoneAnim::Position(Vec3::ZERO))),
+                (1, Some(BoneAnim::Position(Vec3::ZERO))),
+                (
+                    1,
+                    Some(BoneAnim::Rotation(Vec3 {
+                        x: FrameData::Smooth(vec![]),
+                        ..Vec3::ZERO
+                    })),
+                ),
+            ],
+        };
+        let issues = mot.validate(1, &mot_db(), &bone_db());
+        assert_eq!(issues.len(), 3);
+        assert_eq!(
+            issues[0].to_string(),
+            "kl_kubi: bone id 1 appears more than once"
+        );
+        assert_eq!(issues[1].kind, IssueKind::WrongBoneType(BoneType::Rotation));
+        assert_eq!(
+            issues[2].to_string(),
+            "kl_kubi rotation.x: set has no keyframes"
+        );
+
+        let issues = mot.validate(1, &mot_db(), &BoneDatabase::default());
+        assert_eq!(issues[0].kind, IssueKind::NoSkeleton);
+    }
+}
@@ -35,7 +35,6 @@ impl Motion {
             writer.write(&36u32.to_le_bytes())?;
             writer.write(&[0; 16 + 8])?;
             let len = self.sets.len() as u16;
-            println!("set len {}", len);
             // let len  = len + len % 2;
             // println!("pre-len {}", len);
             writer.write(&(len + 1 + 0x3FFF).to_le_bytes())?;
@@ -62,6 +61,39 @@ impl Motion {
 
 use std::io;
 
+impl Motion {
+    ///Writes the motions one after the other in a single file, like the `mot_PVxxx.bin` files
+    ///
+    ///The file starts with a table of one header per motion, ended by an empty one
+    pub fn write_set<W: io::Write>(motions: &[Motion], mut writer: W) -> io::Result<usize> {
+        let mut pos = (motions.len() + 1) * 16;
+        let mut table = vec![];
+        let mut data = vec![];
+        for mot in motions {
+            //Writing the motion where it'll end up keeps its offsets and alignment right,
+            //its own header is dropped afterwards
+            let begin = pos - 32;
+            let mut scratch = io::Cursor::new(vec![0u8; begin]);
+            scratch.set_position(begin as u64);
+            mot.write()(&mut scratch)?;
+            let mut buf = scratch.into_inner();
+            table.extend_from_slice(&(pos as u32).to_le_bytes());
+            table.extend_from_slice(&(pos as u32 + 4).to_le_bytes());
+            table.extend_from_slice(&buf[begin + 8..begin + 16]);
+            buf.drain(..pos);
+            //Bone ids are read until the second 0
+            buf.extend_from_slice(&[0; 4]);
+            buf.resize((buf.len() + 3) / 4 * 4, 0);
+            pos += buf.len();
+            data.append(&mut buf);
+        }
+        table.extend_from_slice(&[0; 16]);
+        writer.write_all(&table)?;
+        writer.write_all(&data)?;
+        Ok(table.len() + data.len())
+    }
+}
+
 impl FrameData {
     pub(crate) fn as_bits(&self) -> u8 {
         use FrameData::*;